@@ -1,11 +1,18 @@
 use std::path::PathBuf;
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use crate::config::ExitPolicy;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub(crate) struct Arguments {
+    /// Profile whose overlay is applied on top of the manifest, e.g. `staging` for `wadm.staging.yaml`
+    #[arg(long, global = true, env = "KUWASH_PROFILE")]
+    pub profile: Option<String>,
 
+    /// Builds a component from a local project instead of its image, e.g. `--local httpserver=../httpserver`
+    #[arg(long, global = true, value_name = "NAME=PATH", value_parser = parse_local)]
+    pub local: Vec<(String, PathBuf)>,
 
     #[command(subcommand)]
     pub command: Commands,
@@ -17,10 +24,52 @@ impl Arguments {
     }
 }
 
+fn parse_local(value: &str) -> Result<(String, PathBuf), String> {
+    match value.split_once('=') {
+        Some((name, path)) if !name.is_empty() && !path.is_empty() => Ok((name.to_string(), PathBuf::from(path))),
+        _ => Err(format!("expected NAME=PATH, got {:?}", value)),
+    }
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     /// runs the current project in development mode with hot reload
-    Dev(DevArgs)
+    Dev(DevArgs),
+    /// removes apps and components left in the lattice by dev sessions that crashed or were killed
+    Clean(CleanArgs),
+    /// compares the components declared in the manifest with what is running in the lattice
+    Status(StatusArgs),
+    /// prints the claims and artifact details of every local component
+    Inspect(InspectArgs),
+    /// checks the tools, the lattice and the project kuwash depends on
+    Doctor(DoctorArgs),
+    /// prints the manifest with the variables expanded and the profile applied
+    Render(RenderArgs),
+    /// reports every problem of the manifest without touching the lattice
+    Validate(ValidateArgs),
+    /// builds all local components of the manifest once
+    Build(BuildArgs),
+    /// shows what deploying the manifest would change compared to the deployed version
+    Diff(DiffArgs),
+    /// builds the local components in release mode, pushes them to the registry and writes a manifest pinned to their digests
+    Release(ReleaseArgs),
+    /// puts and deploys the app, then waits for its local components to run
+    Deploy(DeployArgs),
+    /// lists the versions of an app stored in wadm with when they were put and their images
+    History(HistoryArgs),
+    /// redeploys an earlier version of an app, the one before the deployed version by default
+    Rollback(RollbackArgs),
+    /// undeploys the app
+    Undeploy(UndeployArgs),
+    /// deletes the manifest's version of the app from wadm
+    Delete(DeleteArgs)
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json
 }
 
 #[derive(Args, Debug, Clone)]
@@ -31,8 +80,174 @@ pub struct DevArgs {
     // If you want a simple option for just one actor
     // No required until compound dev mode is ready
     #[arg(long)]
-    pub simple: bool
+    pub simple: bool,
+
+    /// Attach to an app another session is developing: build and watch locally
+    /// without deploying, reloading or cleaning up anything in the lattice
+    #[arg(long, conflicts_with = "take_over")]
+    pub read_only: bool,
+
+    /// Take the app over from another session developing it in the same lattice
+    #[arg(long)]
+    pub take_over: bool,
+
+    /// What to do with the app when dev mode stops. Defaults to `onExit` in kuwash.yaml, then `delete`
+    #[arg(long, value_enum)]
+    pub on_exit: Option<ExitPolicy>,
+
+    /// Seconds to wait for cleanup after SIGINT, SIGTERM or SIGHUP before giving up
+    #[arg(long, default_value_t = 30)]
+    pub cleanup_timeout: u64
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CleanArgs {
+    /// Only print what would be cleaned up
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Remove session records even when some of the cleanup failed
+    #[arg(long)]
+    pub force: bool
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct StatusArgs {
+    /// Path to projects wadm.yaml
+    pub config: PathBuf,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DoctorArgs {
+    /// Path to projects wadm.yaml, enables the checks of its local components
+    pub config: Option<PathBuf>
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct InspectArgs {
+    /// Path to projects wadm.yaml
+    pub config: PathBuf,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RenderArgs {
+    /// Path to projects wadm.yaml
+    pub config: PathBuf
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ValidateArgs {
+    /// Path to projects wadm.yaml
+    pub config: PathBuf
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct BuildArgs {
+    /// Path to projects wadm.yaml
+    pub config: PathBuf
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DiffArgs {
+    /// Path to projects wadm.yaml
+    pub config: PathBuf,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ReleaseArgs {
+    /// Path to projects wadm.yaml
+    pub config: PathBuf,
+
+    /// Registry to push to, e.g. `localhost:5000/kukuana`. Defaults to `registry.url` in kuwash.yaml
+    #[arg(long)]
+    pub registry: Option<String>,
+
+    /// Push over plain HTTP, e.g. to a local registry container
+    #[arg(long)]
+    pub insecure: bool,
+
+    /// Where to write the release manifest, defaults to `<manifest>-release.yaml` next to the manifest
+    #[arg(long)]
+    pub out: Option<PathBuf>
 }
 
+#[derive(Args, Debug, Clone)]
+pub struct DeployArgs {
+    /// Path to projects wadm.yaml
+    pub config: PathBuf,
+
+    /// Build the local components before deploying
+    #[arg(long)]
+    pub build: bool,
+
+    /// Deploy even if a dev session is working on the app
+    #[arg(long)]
+    pub force: bool,
+
+    /// Deploy without asking to confirm the changes to the deployed version
+    #[arg(short, long)]
+    pub yes: bool
+}
 
+#[derive(Args, Debug, Clone)]
+pub struct HistoryArgs {
+    /// Name of the app in wadm
+    pub app_name: String,
 
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RollbackArgs {
+    /// Name of the app in wadm
+    pub app_name: String,
+
+    /// Version to deploy, defaults to the version stored before the deployed one
+    pub version: Option<String>,
+
+    /// Roll back even if a dev session is working on the app
+    #[arg(long)]
+    pub force: bool,
+
+    /// Roll back without asking to confirm the changes to the deployed version
+    #[arg(short, long)]
+    pub yes: bool
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct UndeployArgs {
+    /// Path to projects wadm.yaml
+    pub config: PathBuf,
+
+    /// Undeploy even if a dev session is working on the app
+    #[arg(long)]
+    pub force: bool
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DeleteArgs {
+    /// Path to projects wadm.yaml
+    pub config: PathBuf,
+
+    /// Delete every version of the app, not only the manifest's version
+    #[arg(long)]
+    pub all: bool,
+
+    /// Delete even if a dev session is working on the app
+    #[arg(long)]
+    pub force: bool
+}
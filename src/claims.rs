@@ -0,0 +1,433 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use nkeys::KeyPair;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Custom section of a signed module holding its claims
+const JWT_SECTION: &str = "jwt";
+/// File of a provider archive holding its claims
+pub const PAR_CLAIMS_FILE: &str = "claims.jwt";
+const WASM_MAGIC: &[u8] = b"\0asm";
+/// Version and layer following the magic number of a component-model binary
+const COMPONENT_VERSION: &[u8] = &[0x0d, 0x00, 0x01, 0x00];
+
+/// Claims embedded in a signed actor module
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ActorClaims {
+    /// Module key, the ID the lattice knows the actor by
+    pub public_key: String,
+    pub issuer: String,
+    pub name: Option<String>,
+    /// Contract IDs the actor may use
+    pub capabilities: Vec<String>,
+    pub tags: Vec<String>,
+    pub revision: Option<i32>,
+    pub version: Option<String>,
+    pub call_alias: Option<String>,
+}
+
+/// Claims embedded in a provider archive
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProviderClaims {
+    /// Service key, the ID the lattice knows the provider by
+    pub public_key: String,
+    pub issuer: String,
+    pub name: Option<String>,
+    pub contract_id: String,
+    pub vendor: String,
+    pub revision: Option<i32>,
+    pub version: Option<String>,
+    /// Targets the archive has a binary for, e.g. `x86_64-linux`
+    pub targets: Vec<String>,
+}
+
+/// A component-model (WASI P2) binary without embedded claims.
+/// It has no public key, the lattice knows it by an ID derived from the manifest
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnsignedComponent {
+    pub id: String,
+}
+
+/// The claims of a local artifact, read from the artifact itself
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ComponentClaims {
+    Actor(ActorClaims),
+    Provider(ProviderClaims),
+    Component(UnsignedComponent),
+}
+
+impl ComponentClaims {
+    /// Reads the claims of a `.wasm` module or component or a `.par.gz` provider archive.
+    /// Signatures are not verified, the hosts do that when they start the component.
+    /// `id` identifies a component-model binary that carries no claims
+    pub fn read(path: &Path, id: &str) -> Result<ComponentClaims, String> {
+        let bytes = fs::read(path).map_err(|error| format!("Could not read {:?}: {}", path, error))?;
+
+        if bytes.starts_with(WASM_MAGIC) {
+            let section = custom_section(&bytes, JWT_SECTION)
+                .map_err(|error| format!("{:?} is not a valid wasm module: {}", path, error))?;
+            let token = match section {
+                Some(token) => token,
+                None if bytes[4..].starts_with(COMPONENT_VERSION) => {
+                    return Ok(ComponentClaims::Component(UnsignedComponent { id: id.to_string() }));
+                }
+                None => return Err(format!("{:?} is not signed, it has no `{}` section", path, JWT_SECTION)),
+            };
+            let claims: Claims<ActorMetadata> = decode_jwt(&String::from_utf8_lossy(token))?;
+            return Ok(ComponentClaims::Actor(claims.into()));
+        }
+
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            let token = archive_file(&bytes, PAR_CLAIMS_FILE)
+                .map_err(|error| format!("{:?} is not a valid provider archive: {}", path, error))?
+                .ok_or_else(|| format!("{:?} is not signed, it has no {}", path, PAR_CLAIMS_FILE))?;
+            let claims: Claims<ProviderMetadata> = decode_jwt(&String::from_utf8_lossy(&token))?;
+            return Ok(ComponentClaims::Provider(claims.into()));
+        }
+
+        Err(format!("{:?} is neither a wasm module nor a provider archive", path))
+    }
+
+    /// The claims of a signed actor, None for providers and unsigned components
+    pub fn as_actor(&self) -> Option<&ActorClaims> {
+        match self {
+            ComponentClaims::Actor(claims) => Some(claims),
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ComponentClaims::Actor(_) => "actor",
+            ComponentClaims::Provider(_) => "provider",
+            ComponentClaims::Component(_) => "component",
+        }
+    }
+
+    /// The ID the lattice uses for this component: the module key of an actor,
+    /// the service key of a provider or the derived ID of an unsigned component
+    pub fn get_id(&self) -> String {
+        match self {
+            ComponentClaims::Actor(claims) => claims.public_key.clone(),
+            ComponentClaims::Provider(claims) => claims.public_key.clone(),
+            ComponentClaims::Component(component) => component.id.clone(),
+        }
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        match self {
+            ComponentClaims::Actor(claims) => claims.version.as_deref(),
+            ComponentClaims::Provider(claims) => claims.version.as_deref(),
+            ComponentClaims::Component(_) => None,
+        }
+    }
+}
+
+/// The JWT payload as signed by wascap
+#[derive(Debug, Deserialize)]
+struct Claims<T> {
+    iss: String,
+    sub: String,
+    wascap: Option<T>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ActorMetadata {
+    name: Option<String>,
+    #[serde(default)]
+    caps: Option<Vec<String>>,
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+    rev: Option<i32>,
+    ver: Option<String>,
+    call_alias: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ProviderMetadata {
+    name: Option<String>,
+    #[serde(default)]
+    capid: String,
+    #[serde(default)]
+    vendor: String,
+    rev: Option<i32>,
+    ver: Option<String>,
+    #[serde(default)]
+    target_hashes: HashMap<String, String>,
+}
+
+impl From<Claims<ActorMetadata>> for ActorClaims {
+    fn from(claims: Claims<ActorMetadata>) -> Self {
+        let metadata = claims.wascap.unwrap_or_default();
+        ActorClaims {
+            public_key: claims.sub,
+            issuer: claims.iss,
+            name: metadata.name,
+            capabilities: metadata.caps.unwrap_or_default(),
+            tags: metadata.tags.unwrap_or_default(),
+            revision: metadata.rev,
+            version: metadata.ver,
+            call_alias: metadata.call_alias,
+        }
+    }
+}
+
+impl From<Claims<ProviderMetadata>> for ProviderClaims {
+    fn from(claims: Claims<ProviderMetadata>) -> Self {
+        let metadata = claims.wascap.unwrap_or_default();
+        let mut targets: Vec<String> = metadata.target_hashes.into_keys().collect();
+        targets.sort();
+        ProviderClaims {
+            public_key: claims.sub,
+            issuer: claims.iss,
+            name: metadata.name,
+            contract_id: metadata.capid,
+            vendor: metadata.vendor,
+            revision: metadata.rev,
+            version: metadata.ver,
+            targets,
+        }
+    }
+}
+
+/// Header of the JWTs wascap signs claims with
+const JWT_HEADER: &str = r#"{"typ":"jwt","alg":"Ed25519"}"#;
+
+/// Signs `claims` as a JWT the way wascap does, with the issuer's key
+pub fn encode_jwt<T: Serialize>(claims: &T, issuer: &KeyPair) -> Result<String, String> {
+    let payload = serde_json::to_vec(claims).map_err(|error| format!("Could not serialize the claims: {}", error))?;
+    let signed = format!("{}.{}", URL_SAFE_NO_PAD.encode(JWT_HEADER), URL_SAFE_NO_PAD.encode(payload));
+    let signature = issuer.sign(signed.as_bytes()).map_err(|error| format!("Could not sign the claims: {}", error))?;
+    Ok(format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(signature)))
+}
+
+/// The payload of a JWT, without checking its signature
+fn decode_jwt<T: DeserializeOwned>(token: &str) -> Result<T, String> {
+    let payload = token.trim().split('.').nth(1).ok_or("the embedded claims are not a JWT")?;
+    let json = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('='))
+        .map_err(|error| format!("the embedded claims are not valid base64: {}", error))?;
+    serde_json::from_slice(&json).map_err(|error| format!("the embedded claims are invalid: {}", error))
+}
+
+/// The payload of the first top level custom section called `name` of a wasm module or component
+fn custom_section<'a>(bytes: &'a [u8], name: &str) -> Result<Option<&'a [u8]>, String> {
+    // Magic number and version, or layer for components
+    let mut offset = 8;
+    while offset < bytes.len() {
+        let id = bytes[offset];
+        offset += 1;
+        let size = read_leb128(bytes, &mut offset)? as usize;
+        let end = offset.checked_add(size).filter(|end| *end <= bytes.len()).ok_or("a section runs past the end of the file")?;
+
+        if id == 0 {
+            let mut name_offset = offset;
+            let name_length = read_leb128(bytes, &mut name_offset)? as usize;
+            let name_end = name_offset.checked_add(name_length).filter(|name_end| *name_end <= end).ok_or("a custom section name runs past its section")?;
+            if &bytes[name_offset..name_end] == name.as_bytes() {
+                return Ok(Some(&bytes[name_end..end]));
+            }
+        }
+        offset = end;
+    }
+    Ok(None)
+}
+
+fn read_leb128(bytes: &[u8], offset: &mut usize) -> Result<u64, String> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*offset).ok_or("unexpected end of file")?;
+        *offset += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("invalid LEB128 number".to_string())
+}
+
+/// The content of a file in a gzipped tar archive
+fn archive_file(bytes: &[u8], name: &str) -> Result<Option<Vec<u8>>, String> {
+    let mut archive = tar::Archive::new(GzDecoder::new(bytes));
+    for entry in archive.entries().map_err(|error| error.to_string())? {
+        let mut entry = entry.map_err(|error| error.to_string())?;
+        let matches = entry.path().map(|path| path.file_name().map(|file| file == name).unwrap_or(false)).unwrap_or(false);
+        if matches {
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).map_err(|error| error.to_string())?;
+            return Ok(Some(content));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::path::PathBuf;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use serde_json::json;
+    use super::*;
+
+    fn leb128(mut value: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(byte);
+                return bytes;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+
+    fn section(id: u8, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![id];
+        bytes.extend(leb128(payload.len() as u64));
+        bytes.extend(payload);
+        bytes
+    }
+
+    fn custom(name: &str, content: &[u8]) -> Vec<u8> {
+        let mut payload = leb128(name.len() as u64);
+        payload.extend(name.as_bytes());
+        payload.extend(content);
+        section(0, &payload)
+    }
+
+    fn module(version: &[u8], sections: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = WASM_MAGIC.to_vec();
+        bytes.extend(version);
+        sections.iter().for_each(|section| bytes.extend(section));
+        bytes
+    }
+
+    fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            archive.append_data(&mut header, name, *content).unwrap();
+        }
+        archive.into_inner().unwrap().finish().unwrap()
+    }
+
+    /// Writes `bytes` to a file of its own and reads its claims
+    fn read(name: &str, bytes: &[u8]) -> Result<ComponentClaims, String> {
+        let path: PathBuf = std::env::temp_dir().join(format!("kuwash-claims-{}-{}", std::process::id(), name));
+        fs::File::create(&path).and_then(|mut file| file.write_all(bytes)).unwrap();
+        let claims = ComponentClaims::read(&path, "app-component");
+        let _ = fs::remove_file(&path);
+        claims
+    }
+
+    fn token(wascap: serde_json::Value) -> String {
+        let issuer = KeyPair::new_account();
+        encode_jwt(&json!({ "iss": issuer.public_key(), "sub": "MSUBJECT", "wascap": wascap }), &issuer).unwrap()
+    }
+
+    #[test]
+    fn reads_leb128_numbers() {
+        for value in [0, 1, 127, 128, 624_485, u32::MAX as u64, u64::MAX] {
+            let bytes = leb128(value);
+            let mut offset = 0;
+            assert_eq!(read_leb128(&bytes, &mut offset), Ok(value));
+            assert_eq!(offset, bytes.len());
+        }
+
+        let mut offset = 1;
+        assert_eq!(read_leb128(&[0xff, 0xe5, 0x8e, 0x26, 0x00], &mut offset), Ok(624_485));
+        assert_eq!(offset, 4);
+    }
+
+    #[test]
+    fn rejects_truncated_and_overlong_leb128_numbers() {
+        assert!(read_leb128(&[0x80, 0x80], &mut 0).is_err());
+        assert!(read_leb128(&[], &mut 0).is_err());
+        assert!(read_leb128(&[0x80; 11], &mut 0).is_err());
+    }
+
+    #[test]
+    fn finds_the_named_custom_section() {
+        let bytes = module(&[1, 0, 0, 0], &[section(1, &[0x60, 0, 0]), custom("name", b"other"), custom("jwt", b"token"), custom("jwt", b"second")]);
+        assert_eq!(custom_section(&bytes, "jwt"), Ok(Some(&b"token"[..])));
+        assert_eq!(custom_section(&bytes, "producers"), Ok(None));
+        assert_eq!(custom_section(&module(&[1, 0, 0, 0], &[]), "jwt"), Ok(None));
+        assert_eq!(custom_section(WASM_MAGIC, "jwt"), Ok(None));
+    }
+
+    #[test]
+    fn rejects_sections_running_past_their_bounds() {
+        let mut truncated = module(&[1, 0, 0, 0], &[custom("jwt", b"token")]);
+        truncated.pop();
+        assert!(custom_section(&truncated, "jwt").is_err());
+
+        // The name claims more bytes than its section has
+        let bytes = module(&[1, 0, 0, 0], &[section(0, &[10, b'j', b'w', b't'])]);
+        assert!(custom_section(&bytes, "jwt").is_err());
+
+        let huge = module(&[1, 0, 0, 0], &[vec![0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]]);
+        assert!(custom_section(&huge, "jwt").is_err());
+        assert!(custom_section(&module(&[1, 0, 0, 0], &[vec![0]]), "jwt").is_err());
+    }
+
+    #[test]
+    fn finds_files_in_archives() {
+        let bytes = archive(&[("x86_64-linux.bin", b"binary"), ("claims.jwt", b"token")]);
+        assert_eq!(archive_file(&bytes, PAR_CLAIMS_FILE), Ok(Some(b"token".to_vec())));
+        assert_eq!(archive_file(&bytes, "aarch64-linux.bin"), Ok(None));
+        assert!(archive_file(&bytes[..bytes.len() / 2], "missing").is_err());
+        assert!(archive_file(&[0x1f, 0x8b, 0, 1, 2, 3], PAR_CLAIMS_FILE).is_err());
+    }
+
+    #[test]
+    fn reads_the_claims_of_signed_modules() {
+        let token = token(json!({ "name": "echo", "caps": ["wasmcloud:httpserver"], "rev": 2, "ver": "0.1.0" }));
+        let claims = read("actor.wasm", &module(&[1, 0, 0, 0], &[custom(JWT_SECTION, token.as_bytes())])).unwrap();
+        let actor = claims.as_actor().unwrap();
+        assert_eq!(actor.public_key, "MSUBJECT");
+        assert_eq!(actor.name.as_deref(), Some("echo"));
+        assert_eq!(actor.capabilities, vec!["wasmcloud:httpserver"]);
+        assert_eq!(actor.revision, Some(2));
+        assert_eq!(claims.version(), Some("0.1.0"));
+    }
+
+    #[test]
+    fn knows_unsigned_components_by_their_id() {
+        let claims = read("component.wasm", &module(COMPONENT_VERSION, &[custom("producers", b"")])).unwrap();
+        assert_eq!(claims, ComponentClaims::Component(UnsignedComponent { id: "app-component".to_string() }));
+        assert_eq!(claims.get_id(), "app-component");
+    }
+
+    #[test]
+    fn reads_the_claims_of_provider_archives() {
+        let token = token(json!({ "capid": "wasmcloud:httpserver", "vendor": "wasmCloud", "target_hashes": { "x86_64-linux": "A", "aarch64-linux": "B" } }));
+        let claims = read("provider.par.gz", &archive(&[(PAR_CLAIMS_FILE, token.as_bytes())])).unwrap();
+        match claims {
+            ComponentClaims::Provider(provider) => {
+                assert_eq!(provider.contract_id, "wasmcloud:httpserver");
+                assert_eq!(provider.targets, vec!["aarch64-linux", "x86_64-linux"]);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_unsigned_and_unknown_artifacts() {
+        assert!(read("core.wasm", &module(&[1, 0, 0, 0], &[])).unwrap_err().contains("is not signed"));
+        assert!(read("empty.par.gz", &archive(&[("x86_64-linux.bin", b"binary")])).unwrap_err().contains("is not signed"));
+        assert!(read("garbage.wasm", &module(&[1, 0, 0, 0], &[custom(JWT_SECTION, b"not a token")])).is_err());
+        assert!(read("short.wasm", WASM_MAGIC).unwrap_err().contains("is not signed"));
+        assert!(read("text.wasm", b"hello").unwrap_err().contains("neither"));
+    }
+}
@@ -1,4 +1,18 @@
+pub mod app;
+pub mod build;
+pub mod clean;
 pub mod dev;
+pub mod diff;
+pub mod doctor;
+pub mod history;
+pub mod inspect;
 pub mod managed;
 mod simple;
-mod component_data;
\ No newline at end of file
+pub mod release;
+pub mod render;
+pub mod rollback;
+pub mod status;
+pub mod validate;
+mod component_data;
+mod reload;
+
@@ -0,0 +1,135 @@
+use std::io::{self, IsTerminal, Write};
+use std::process::exit;
+use wadm::model::Manifest;
+use crate::commands::component_data::ComponentData;
+use crate::commands::diff::deployed_manifest;
+use crate::diff::ManifestDiff;
+use crate::helper::Helper;
+use crate::lock::SessionLock;
+use crate::logger::Logger;
+use crate::progress::Progress;
+
+/// The application lifecycle steps of dev mode as one shot commands:
+/// put and deploy the app, roll it back, undeploy it or delete it.
+///
+/// Every command exits with a non zero code when it fails, so they can be used in scripts and CI.
+/// They refuse to touch an app a dev session is working on unless forced.
+pub struct AppCommand {
+    pub manifest: Manifest,
+    pub force: bool,
+}
+
+impl AppCommand {
+    pub fn new(manifest: Manifest, force: bool) -> AppCommand {
+        AppCommand {
+            manifest,
+            force,
+        }
+    }
+
+    /// Puts and deploys the app, optionally building the local components first,
+    /// then waits for the local components to run
+    pub fn deploy(&self, build: bool, yes: bool) {
+        self.ensure_not_in_use();
+        self.confirm_changes(yes);
+
+        let state = ComponentData::from_manifest(&self.manifest);
+        if build && !state.build_all() {
+            exit(1);
+        }
+
+        let app_name = &self.manifest.metadata.name;
+        let mut task = Progress::spinner(format!("Putting app {}", app_name));
+        Helper::put_manifest(&self.manifest);
+
+        task.set_message(format!("Deploying app {}", app_name));
+        Helper::deploy_app(&self.manifest);
+        task.finish(format!("Deployed app {}:{}", app_name, self.manifest.version()));
+
+        if !state.wait_until_running() {
+            exit(1);
+        }
+    }
+
+    /// Deploys the manifest's version, which wadm already stores, instead of putting it again
+    pub fn rollback(&self, yes: bool) {
+        self.ensure_not_in_use();
+        self.confirm_changes(yes);
+
+        let state = ComponentData::from_manifest(&self.manifest);
+        let app_name = &self.manifest.metadata.name;
+        let version = self.manifest.version();
+        let task = Progress::spinner(format!("Rolling back app {} to {}", app_name, version));
+        Helper::deploy_app_version(app_name, version);
+        task.finish(format!("Deployed app {}:{}", app_name, version));
+
+        if !state.wait_until_running() {
+            exit(1);
+        }
+    }
+
+    pub fn undeploy(&self) {
+        self.ensure_not_in_use();
+
+        if !Helper::undeploy_app_by_name(&self.manifest.metadata.name) {
+            exit(1);
+        }
+    }
+
+    /// Deletes the manifest's version of the app, or every version with `all`
+    pub fn delete(&self, all: bool) {
+        self.ensure_not_in_use();
+
+        let deleted = if all {
+            Helper::delete_app(&self.manifest)
+        } else {
+            Helper::delete_app_version(&self.manifest.metadata.name, self.manifest.version())
+        };
+
+        if !deleted {
+            exit(1);
+        }
+    }
+
+    /// Shows the changes to the deployed version and asks to go ahead, unless `yes` is set
+    fn confirm_changes(&self, yes: bool) {
+        let deployed = deployed_manifest(&self.manifest.metadata.name);
+        let diff = ManifestDiff::between(deployed.as_ref(), &self.manifest);
+        if diff.is_empty() {
+            Logger::info(format!("No changes to the deployed version of {}", self.manifest.metadata.name));
+            return;
+        }
+
+        Progress::suspend(|| diff.print());
+        if yes {
+            return;
+        }
+
+        if !io::stdin().is_terminal() {
+            Logger::error_and_exit("Refusing to deploy without confirmation, pass --yes to deploy non interactively".to_string());
+        }
+
+        print!("\nDeploy these changes? [y/N] ");
+        let _ = io::stdout().flush();
+        let mut answer = String::new();
+        let _ = io::stdin().read_line(&mut answer);
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            println!("Deploy cancelled");
+            exit(1);
+        }
+    }
+
+    fn ensure_not_in_use(&self) {
+        let lattice = Helper::get_lattice(&Helper::get_hosts());
+        let app_name = &self.manifest.metadata.name;
+
+        if let Some(owner) = SessionLock::owner(&lattice, app_name) {
+            if !self.force {
+                Logger::error_and_exit(format!(
+                    "App {} on lattice {} is being developed by the session with pid {} ({:?}). Pass --force to change it anyway",
+                    app_name, lattice, owner.pid, owner.manifest_path
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use std::process::exit;
+use wadm::model::Manifest;
+use crate::arguments::BuildArgs;
+use crate::commands::component_data::ComponentData;
+use crate::logger::Logger;
+
+/// Builds every local (`file://`) component of the manifest once
+pub struct BuildCommand {
+    pub manifest: Manifest,
+    pub arguments: BuildArgs,
+}
+
+impl BuildCommand {
+    pub fn new(manifest: Manifest, arguments: &BuildArgs) -> BuildCommand {
+        BuildCommand {
+            manifest,
+            arguments: arguments.clone(),
+        }
+    }
+
+    pub fn start(&self) {
+        let state = ComponentData::from_manifest(&self.manifest);
+        if state.is_empty() {
+            Logger::info(format!("No local actors or providers found in {:?}, nothing to build", self.arguments.config));
+            return;
+        }
+
+        if !state.build_all() {
+            exit(1);
+        }
+    }
+}
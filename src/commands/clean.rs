@@ -0,0 +1,127 @@
+use std::process::exit;
+use crate::arguments::CleanArgs;
+use crate::helper::Helper;
+use crate::logger::Logger;
+use crate::session::Session;
+
+/// Removes what crashed or killed dev sessions left behind in the lattice.
+///
+/// A session is stale when the process that wrote its record is no longer running.
+/// For every stale session the app is undeployed in the lattice the session used,
+/// the versions it put are deleted and any of its components still running are stopped.
+pub struct CleanCommand {
+    pub arguments: CleanArgs,
+}
+
+impl CleanCommand {
+    pub fn new(arguments: &CleanArgs) -> CleanCommand {
+        CleanCommand {
+            arguments: arguments.clone(),
+        }
+    }
+
+    pub fn start(&self) {
+        let (live, stale): (Vec<Session>, Vec<Session>) = Session::load_all()
+            .into_iter()
+            .partition(|session| session.is_alive());
+
+        if stale.is_empty() {
+            Logger::info(format!("No stale sessions found in {:?}", Session::directory()));
+            return;
+        }
+
+        let mut failed = false;
+        for session in &stale {
+            Logger::info(format!(
+                "Found stale session for app {} in lattice {} (pid {}, manifest {:?})",
+                session.app_name, lattice_name(session), session.pid, session.manifest_path
+            ));
+
+            if let Some(owner) = live.iter().find(|live| live.same_app(session)) {
+                Logger::info(format!("App {} is in use by the running session with pid {}, only removing the stale record", session.app_name, owner.pid));
+                if !self.arguments.dry_run {
+                    session.remove();
+                }
+                continue;
+            }
+
+            if self.arguments.dry_run {
+                Logger::info(format!(
+                    "Would undeploy app {}, delete versions [{}] and stop components [{}]",
+                    session.app_name,
+                    session.versions.join(", "),
+                    session.components.iter().map(|component| component.name.as_str()).collect::<Vec<_>>().join(", ")
+                ));
+                continue;
+            }
+
+            failed |= !self.clean_session(session);
+        }
+
+        if failed {
+            exit(1);
+        }
+    }
+
+    fn clean_session(&self, session: &Session) -> bool {
+        // Records without a lattice use the current wash context, not the lattice of the previous session
+        Helper::target_lattice(session.lattice.as_deref());
+
+        let cleaned = match self.clean_lattice(session) {
+            Ok(cleaned) => cleaned,
+            Err(error) => {
+                Logger::error(format!("Could not clean up app {} in lattice {}: {}", session.app_name, lattice_name(session), error.trim()));
+                false
+            }
+        };
+
+        if cleaned || self.arguments.force {
+            session.remove();
+        } else {
+            Logger::error(format!("Kept session record {:?}, run `kuwash clean` again or pass --force to drop it", session.path()));
+        }
+
+        cleaned
+    }
+
+    /// Undeploys the app, deletes its versions and stops its components. Returns whether
+    /// everything is cleaned up, errors are those of wash or wadm, e.g. an unreachable lattice
+    fn clean_lattice(&self, session: &Session) -> Result<bool, String> {
+        // An app that is gone or undeployed already counts as cleaned up
+        let mut cleaned = true;
+        match Helper::try_get_app(&session.app_name)? {
+            None => Logger::info(format!("App {} is no longer in lattice {}, nothing to undeploy", session.app_name, lattice_name(session))),
+            Some(_) => {
+                if !Helper::undeploy_app_by_name(&session.app_name) {
+                    cleaned &= Helper::try_get_app(&session.app_name)?.and_then(|app| app.deployed_version).is_none();
+                }
+
+                let stored: Vec<String> = Helper::try_get_app_versions(&session.app_name)?.into_iter().map(|version| version.version).collect();
+                for version in session.versions.iter().filter(|version| stored.contains(version)) {
+                    cleaned &= Helper::delete_app_version(&session.app_name, version);
+                }
+            }
+        }
+
+        let inventory = Helper::try_get_host_inventory()?;
+        for component in &session.components {
+            let actor_running = inventory.iter().any(|host| host.actors.iter().any(|actor| actor.id == component.id));
+            let provider_running = inventory.iter().any(|host| host.providers.iter().any(|provider| provider.id == component.id));
+
+            if actor_running {
+                Helper::stop_actor(&component.id);
+            }
+
+            if let (true, Some(contract_id)) = (provider_running, &component.contract_id) {
+                Helper::stop_provider(&component.id, component.link_name.as_deref().unwrap_or("default"), contract_id, None);
+            }
+        }
+
+        Ok(cleaned)
+    }
+}
+
+/// The lattice of a session for messages, `current` for records that did not store it
+fn lattice_name(session: &Session) -> &str {
+    session.lattice.as_deref().unwrap_or("current")
+}
@@ -1,7 +1,17 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use std::sync::Arc;
-use wadm::model::{Component};
-use crate::helper::{ComponentClaims};
+use std::time::{Duration, Instant};
+use wadm::model::{Component, Manifest, Properties};
+use crate::claims::ComponentClaims;
+use crate::helper::Helper;
+use crate::logger::Logger;
+use crate::progress::Progress;
+use crate::schema;
+
+/// How long to wait for the lattice to report local components as running after a deploy
+const RECONCILE_TIMEOUT: Duration = Duration::from_secs(120);
+const RECONCILE_POLL_INTERVAL: Duration = Duration::from_secs(2);
 
 #[derive(Debug, Clone)]
 pub struct ComponentData {
@@ -20,6 +30,58 @@ impl ComponentData {
         }
     }
 
+    /// Sets up a local state of the application
+    /// capturing the components in the manifest with a `file://` image and
+    /// the claims in their images. Artifacts that were not built yet are built first
+    pub fn from_manifest(manifest: &Manifest) -> Self {
+        let mut state = ComponentData::new();
+
+        for component in manifest.spec.components.iter() {
+            let image = match &component.properties {
+                Properties::Actor { properties } => &properties.image,
+                Properties::Capability { properties } => &properties.image,
+            };
+
+            let image_path = match Helper::local_image_path(image) {
+                Some(path) => path,
+                None => {
+                    Logger::info(format!("Skipping non local component in: {}", image));
+                    continue;
+                }
+            };
+
+            let repo_path = Helper::local_repo_path(&image_path);
+
+            if !Path::new(&image_path).exists() {
+                Logger::info(format!("{} has not been built yet, building it to read its claims", image_path));
+                let built = match &component.properties {
+                    Properties::Actor { .. } => Helper::build_actor(&repo_path),
+                    Properties::Capability { properties } => Helper::build_provider(properties),
+                };
+                if !built {
+                    Logger::error_and_exit(format!("Could not build component {} at {}", component.name, repo_path));
+                }
+            }
+
+            // There may be a case when someone uses the wrong image
+            let claims = Helper::inspect_images(image_path.clone(), &schema::component_id(manifest, component));
+            match (&component.properties, &claims) {
+                (properties, claims) if matches_properties(properties, claims) => {
+                    let id = claims.get_id();
+                    state.add_item(component.name.clone(), repo_path, id, component.clone(), claims.clone());
+                }
+                (Properties::Actor { .. }, _) => {
+                    Logger::error_and_exit(format!("Oops, the provider image {} is not an actor", image));
+                }
+                (Properties::Capability { .. }, _) => {
+                    Logger::error_and_exit(format!("Oops, the provider image {} is not a provider", image));
+                }
+            }
+        }
+
+        state
+    }
+
     // Method to add a new item
     pub fn add_item(&mut self, name: String, path: String, id: String, component: Component, claims: ComponentClaims) {
         let data = Arc::new((component, claims));
@@ -28,10 +90,18 @@ impl ComponentData {
         self.id_map.insert(id, data);
     }
 
-    // Method to get an item by name
-    pub fn get_by_name(&self, name: &str) -> (Component, ComponentClaims) {
-        let reference = self.name_map.get(name).cloned().unwrap();
-        (*reference).clone()
+    /// Replaces the claims of the component built from `path`, e.g. after a rebuild,
+    /// and returns the claims it had before
+    pub fn update_claims(&mut self, path: &str, claims: ComponentClaims) -> ComponentClaims {
+        let (component, old) = self.get_by_path(path);
+        self.id_map.remove(&old.get_id());
+        self.add_item(component.name.clone(), path.to_string(), claims.get_id(), component, claims);
+        old
+    }
+
+    /// Claims keyed by component name
+    pub fn get_claims(&self) -> HashMap<String, ComponentClaims> {
+        self.name_map.iter().map(|(name, data)| (name.clone(), data.1.clone())).collect()
     }
 
     // Method to get an item by path
@@ -61,4 +131,243 @@ impl ComponentData {
         self.path_map.keys().cloned().collect()
     }
 
-}
\ No newline at end of file
+    /// Builds every local component in parallel, with one progress spinner per build.
+    /// A build only succeeds when its artifact is still the kind of component the manifest
+    /// declares. Returns whether all builds succeeded
+    pub fn build_all(&self) -> bool {
+        let builds: Vec<(String, Component, ComponentClaims)> = self.get_paths().into_iter()
+            .map(|path| {
+                let (component, claims) = self.get_by_path(&path);
+                (path, component, claims)
+            })
+            .collect();
+
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = builds.iter().map(|(path, component, claims)| {
+                scope.spawn(move || {
+                    let task = Progress::spinner(format!("Building {}", component.name));
+                    let (image, built) = match &component.properties {
+                        Properties::Actor { properties } => (&properties.image, Helper::build_actor(path)),
+                        Properties::Capability { properties } => (&properties.image, Helper::build_provider(properties)),
+                    };
+                    if !built {
+                        task.fail(format!("Failed to build {}", component.name));
+                        return false;
+                    }
+
+                    let artifact = Helper::local_image_path(image).unwrap_or_default();
+                    match Helper::try_inspect_image(&artifact, &claims.get_id()) {
+                        Ok(rebuilt) if matches_properties(&component.properties, &rebuilt) => {
+                            task.finish(format!("Built {}", component.name));
+                            true
+                        }
+                        Ok(rebuilt) => {
+                            task.fail(format!("{} now builds to a {}, not the {} of the manifest", component.name, rebuilt.kind(), claims.kind()));
+                            false
+                        }
+                        Err(error) => {
+                            task.fail(format!("Could not read the claims of {}: {}", component.name, error.trim()));
+                            false
+                        }
+                    }
+                })
+            }).collect();
+
+            handles.into_iter().all(|handle| handle.join().unwrap_or(false))
+        })
+    }
+
+    /// Polls the lattice inventory until every local component is reported as running
+    /// or `RECONCILE_TIMEOUT` is reached. Returns whether all of them came up
+    pub fn wait_until_running(&self) -> bool {
+        let expected: Vec<(String, String)> = self.get_components().iter()
+            .map(|(component, claims)| (component.name.clone(), claims.get_id()))
+            .collect();
+
+        let task = Progress::bar(expected.len() as u64, "Waiting for components to start".to_string());
+        let started = Instant::now();
+        let mut last_count = 0;
+
+        loop {
+            let running: HashSet<String> = Helper::get_host_inventory().iter()
+                .flat_map(|inventory| {
+                    inventory.actors.iter().map(|actor| actor.id.clone())
+                        .chain(inventory.providers.iter().map(|provider| provider.id.clone()))
+                })
+                .collect();
+
+            let pending: Vec<&String> = expected.iter()
+                .filter(|(_, id)| !running.contains(id))
+                .map(|(name, _)| name)
+                .collect();
+
+            let count = expected.len() - pending.len();
+            if count != last_count {
+                task.set_position(count as u64);
+                last_count = count;
+            }
+
+            if pending.is_empty() {
+                task.finish(format!("All {} local components are running", expected.len()));
+                return true;
+            }
+
+            if started.elapsed() > RECONCILE_TIMEOUT {
+                task.fail(format!("Timed out waiting for: {}", pending.iter().map(|name| name.as_str()).collect::<Vec<_>>().join(", ")));
+                return false;
+            }
+
+            std::thread::sleep(RECONCILE_POLL_INTERVAL);
+        }
+    }
+
+}
+
+/// Whether an artifact is the kind of component the manifest declares:
+/// actors may be signed modules or unsigned components
+pub fn matches_properties(properties: &Properties, claims: &ComponentClaims) -> bool {
+    matches!(
+        (properties, claims),
+        (Properties::Actor { .. }, ComponentClaims::Actor(_) | ComponentClaims::Component(_)) | (Properties::Capability { .. }, ComponentClaims::Provider(_))
+    )
+}
+
+/// What changed between the claims of two builds of the same component
+pub fn claims_changes(old: &ComponentClaims, new: &ComponentClaims) -> Vec<String> {
+    let mut changes = Vec::new();
+    match (old, new) {
+        (ComponentClaims::Actor(old), ComponentClaims::Actor(new)) => {
+            if old.public_key != new.public_key {
+                changes.push(format!("public key changed from {} to {}", old.public_key, new.public_key));
+            }
+            let added: Vec<&String> = new.capabilities.iter().filter(|c| !old.capabilities.contains(c)).collect();
+            let removed: Vec<&String> = old.capabilities.iter().filter(|c| !new.capabilities.contains(c)).collect();
+            if !added.is_empty() {
+                changes.push(format!("capabilities added: {}", added.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(", ")));
+            }
+            if !removed.is_empty() {
+                changes.push(format!("capabilities removed: {}", removed.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(", ")));
+            }
+            if old.call_alias != new.call_alias {
+                changes.push(format!("call alias changed from {:?} to {:?}", old.call_alias, new.call_alias));
+            }
+        }
+        (ComponentClaims::Provider(old), ComponentClaims::Provider(new)) => {
+            if old.public_key != new.public_key {
+                changes.push(format!("public key changed from {} to {}", old.public_key, new.public_key));
+            }
+            if old.contract_id != new.contract_id {
+                changes.push(format!("contract changed from {} to {}", old.contract_id, new.contract_id));
+            }
+        }
+        (ComponentClaims::Component(old), ComponentClaims::Component(new)) => {
+            if old.id != new.id {
+                changes.push(format!("ID changed from {} to {}", old.id, new.id));
+            }
+        }
+        (old, new) => changes.push(format!("the {} now builds to {} {}", old.kind(), if new.kind() == "actor" { "an" } else { "a" }, new.kind())),
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::claims::{ActorClaims, ProviderClaims, UnsignedComponent};
+    use super::*;
+
+    fn actor(public_key: &str, capabilities: &[&str], call_alias: Option<&str>) -> ComponentClaims {
+        ComponentClaims::Actor(ActorClaims {
+            public_key: public_key.to_string(),
+            issuer: "AISSUER".to_string(),
+            name: Some("echo".to_string()),
+            capabilities: capabilities.iter().map(|capability| capability.to_string()).collect(),
+            tags: Vec::new(),
+            revision: Some(1),
+            version: Some("0.1.0".to_string()),
+            call_alias: call_alias.map(String::from),
+        })
+    }
+
+    fn provider(public_key: &str, contract_id: &str) -> ComponentClaims {
+        ComponentClaims::Provider(ProviderClaims {
+            public_key: public_key.to_string(),
+            issuer: "AISSUER".to_string(),
+            name: Some("httpserver".to_string()),
+            contract_id: contract_id.to_string(),
+            vendor: "NONE".to_string(),
+            revision: Some(1),
+            version: Some("0.1.0".to_string()),
+            targets: vec!["x86_64-linux".to_string()],
+        })
+    }
+
+    fn component(id: &str) -> ComponentClaims {
+        ComponentClaims::Component(UnsignedComponent { id: id.to_string() })
+    }
+
+    #[test]
+    fn ignores_rebuilds_with_the_same_claims() {
+        let echo = actor("MECHO", &["wasmcloud:httpserver"], None);
+        assert!(claims_changes(&echo, &echo.clone()).is_empty());
+        assert!(claims_changes(&provider("VHTTP", "wasmcloud:httpserver"), &provider("VHTTP", "wasmcloud:httpserver")).is_empty());
+        assert!(claims_changes(&component("hello-http"), &component("hello-http")).is_empty());
+    }
+
+    #[test]
+    fn reports_changed_actor_claims() {
+        let old = actor("MECHO", &["wasmcloud:httpserver", "wasmcloud:keyvalue"], None);
+        let new = actor("MECHO2", &["wasmcloud:httpserver", "wasmcloud:messaging", "wasmcloud:logging"], Some("echo"));
+        assert_eq!(claims_changes(&old, &new), vec![
+            "public key changed from MECHO to MECHO2",
+            "capabilities added: wasmcloud:messaging, wasmcloud:logging",
+            "capabilities removed: wasmcloud:keyvalue",
+            "call alias changed from None to Some(\"echo\")",
+        ]);
+    }
+
+    #[test]
+    fn reports_changed_provider_and_component_claims() {
+        assert_eq!(claims_changes(&provider("VHTTP", "wasmcloud:httpserver"), &provider("VHTTP2", "wasi:http")), vec![
+            "public key changed from VHTTP to VHTTP2",
+            "contract changed from wasmcloud:httpserver to wasi:http",
+        ]);
+        assert_eq!(claims_changes(&component("hello-http"), &component("hello-api")), vec!["ID changed from hello-http to hello-api"]);
+    }
+
+    #[test]
+    fn reports_a_change_of_kind() {
+        assert_eq!(claims_changes(&component("hello-http"), &actor("MECHO", &[], None)), vec!["the component now builds to an actor"]);
+        assert_eq!(claims_changes(&actor("MECHO", &[], None), &component("hello-http")), vec!["the actor now builds to a component"]);
+        assert_eq!(claims_changes(&actor("MECHO", &[], None), &provider("VHTTP", "wasmcloud:httpserver")), vec!["the actor now builds to a provider"]);
+    }
+
+    #[test]
+    fn matches_artifacts_to_the_kind_the_manifest_declares() {
+        let manifest: Manifest = serde_yaml::from_str("
+apiVersion: core.oam.dev/v1beta1
+kind: Application
+metadata:
+  name: echo
+  annotations:
+    version: v0.0.1
+spec:
+  components:
+    - name: echo
+      type: actor
+      properties:
+        image: file:///echo.wasm
+    - name: httpserver
+      type: capability
+      properties:
+        image: file:///httpserver.par.gz
+        contract: wasmcloud:httpserver
+").unwrap();
+        let (actor_properties, capability_properties) = (&manifest.spec.components[0].properties, &manifest.spec.components[1].properties);
+
+        assert!(matches_properties(actor_properties, &actor("MECHO", &[], None)));
+        assert!(matches_properties(actor_properties, &component("echo-echo")));
+        assert!(!matches_properties(actor_properties, &provider("VHTTP", "wasmcloud:httpserver")));
+        assert!(matches_properties(capability_properties, &provider("VHTTP", "wasmcloud:httpserver")));
+        assert!(!matches_properties(capability_properties, &component("echo-httpserver")));
+    }
+}
@@ -1,16 +1,24 @@
 use std::collections::{HashSet};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
-use wadm::model::{Component, Manifest, Properties, TraitProperty};
+use wadm::model::{Component, Manifest, Properties};
 use wasmcloud_interface_lattice_control::{Hosts, ActorDescriptions, Host};
-use regex::Regex;
 use crate::arguments::DevArgs;
-use crate::helper::{ComponentClaims, Helper, StoredActorClaims, StoredProviderClaims};
+use crate::claims::ComponentClaims;
+use crate::helper::Helper;
 use crate::helper::LovalHostInventory;
+use crate::config::{ExitPolicy, ProjectConfig};
+use crate::lock::SessionLock;
 use crate::logger::Logger;
 use notify::{Watcher, RecursiveMode};
 use notify_debouncer_full::new_debouncer;
-use crate::commands::component_data::ComponentData;
+use crate::commands::component_data::{claims_changes, matches_properties, ComponentData};
+use crate::progress::Progress;
+use crate::commands::reload::ProviderReload;
+use crate::schema;
+use crate::session::{Session, SessionComponent};
+use crate::shutdown::{CleanupStep, Shutdown};
+use crate::validation::{self, Validator};
 
 
 #[derive(Debug)]
@@ -20,7 +28,11 @@ pub struct DevCommand {
     pub actors: ActorDescriptions,
     pub manifest: Manifest,
     pub arguments: DevArgs,
-    pub state: ComponentData
+    pub state: ComponentData,
+    pub session: Session,
+    /// None when attached read-only
+    pub lock: Option<SessionLock>,
+    pub config: ProjectConfig
 }
 
 impl DevCommand {
@@ -38,13 +50,33 @@ impl DevCommand {
     /// being stopped, so it is not required to redeploy the application.
     pub fn simple(&mut self) {
 
+        Shutdown::on_signal(Duration::from_secs(self.arguments.cleanup_timeout));
+
+        validation::preflight(&self.manifest, &self.arguments.config);
+
         self.setup_image_maping();
 
         if self.state.is_empty() {
             Logger::error_and_exit("No local actors or providers found in manifest. Cannot run dev mode".to_string());
         }
 
-        self.initial_build();
+        if self.arguments.read_only {
+            Logger::info(format!("Attaching read-only to app {}, the lattice will not be changed", self.manifest.metadata.name));
+            if !self.initial_build() {
+                Logger::warn("The initial build failed, watching for changes to fix it".to_string());
+            }
+            self.listen_for_changes_and_redeploy();
+            return;
+        }
+
+        self.acquire_lock();
+
+        self.record_session();
+
+        if !self.initial_build() {
+            self.abandon_session();
+            Logger::error_and_exit("Not deploying since the initial build failed, fix it and start dev mode again".to_string());
+        }
 
         self.deploy();
 
@@ -57,6 +89,8 @@ impl DevCommand {
     /// update actors and providers on change
     #[allow(dead_code)]
     pub fn managed(&mut self) {
+        validation::preflight(&self.manifest, &self.arguments.config);
+
         self.setup_image_maping();
 
         if self.state.is_empty() {
@@ -65,8 +99,6 @@ impl DevCommand {
 
         self.initial_build();
 
-        self.validate_actor_claims();
-
     }
 }
 
@@ -86,17 +118,12 @@ impl DevCommand {
             debouncer.cache().add_root(path, RecursiveMode::Recursive);
         }
 
-        let m = self.manifest.clone();
-        ctrlc::set_handler(move || {
-            Logger::info("Cleaning up".to_string());
-            cleanup(&m);
-            std::process::exit(0);
-        }).expect("Error setting Ctrl-C handler");
-
-
-
         // print all events and errors
         for result in rx {
+            if Shutdown::is_shutting_down() {
+                continue;
+            }
+
             match result {
                 Ok(events) => {
                     let mut path_set: HashSet<String> = HashSet::new();
@@ -108,49 +135,20 @@ impl DevCommand {
                         }
                     }
 
-                    path_set.iter().for_each(|path| {
-
-                        let (component, claims) = self.state.get_by_path(path);
-                        let p = path.clone();
-
-                        match &component.properties {
-                            Properties::Actor {
-                                properties,
-                            } => {
-                                Logger::info(format!("Rebuilding actor: {}", properties.image));
-                                if let ComponentClaims::Actor(StoredActorClaims { module, .. }) = claims {
-                                    let id = module.clone();
-                                    std::thread::spawn(move || {
-                                        // Reset the actor
-                                        // If build is successful
-                                        // stopping the actor will cause wasmcloud to reload it
-                                        // when it compares it's state to the manifest
-                                        // and will redeploy the actor
-                                        // with the latest image
-                                        if Helper::build_actor(&p) {
-                                            Helper::stop_actor(&id);
-                                        }
-                                    });
-                                }
-
-                            }
-                            Properties::Capability {
-                                properties
-                            } => {
-                                Logger::info(format!("Rebuilding provider: {}", properties.image));
-                                if let ComponentClaims::Provider(StoredProviderClaims { capability_contract_id, service, ..  }) = claims {
-                                    let id = service.clone();
-                                    let contract_id = capability_contract_id.clone();
-
-                                    if Helper::build_provider(&p) {
-                                        Helper::stop_provider(&id, &contract_id);
-                                        // Helper::start_provider(&properties.image)
-                                    }
-                                }
-                            }
-                        }
-
-                    })
+                    // Build in parallel, then take in the claims of every successful build
+                    let lock = &self.lock;
+                    let state = &self.state;
+                    let manifest = &self.manifest;
+                    let rebuilt: Vec<(String, ComponentClaims)> = std::thread::scope(|scope| {
+                        let handles: Vec<_> = path_set.iter()
+                            .map(|path| scope.spawn(move || rebuild(path, manifest, state, lock)))
+                            .collect();
+                        handles.into_iter().filter_map(|handle| handle.join().ok().flatten()).collect()
+                    });
+
+                    for (path, claims) in rebuilt {
+                        self.refresh_claims(&path, claims);
+                    }
                 },
                 Err(errors) => errors.iter().for_each(|error| println!("{error:?}")),
             }
@@ -159,135 +157,97 @@ impl DevCommand {
     }
 
 
-    fn initial_build(&mut self) {
-        for path in self.state.get_paths() {
-            let (component, claims) = self.state.get_by_path(&path);
-
-
-            // let path = path.clone();
-            match &component.properties {
-                Properties::Actor { .. } => {
-                    if let ComponentClaims::Actor(_) = claims {
-                        Logger::info(format!("Building actor: {}", path));
-                        Helper::build_actor(&path);
-                    }
-                }
-                Properties::Capability { .. } => {
-                    if let ComponentClaims::Provider(_) = claims {
-                        Helper::build_provider(&path);
-                    }
-                }
-            }
+    /// Takes in the claims of a rebuilt component. Changes are reported, the session
+    /// record follows a new public key and the links are checked against the new capabilities
+    fn refresh_claims(&mut self, path: &str, claims: ComponentClaims) {
+        let (component, old) = self.state.get_by_path(path);
+        let changes = claims_changes(&old, &claims);
+        if changes.is_empty() {
+            return;
         }
-    }
-
-    /// Sets up a local state of the application
-    /// capturing the components in the manifest and
-    /// the claims in their images. All stored in self.state
-    fn setup_image_maping(&mut self) {
-
-        for component in self.manifest.spec.components.iter() {
-
-            match &component.properties {
-                Properties::Actor {
-                    properties
-                } => {
-                    let image = &properties.image;
-                    if image.starts_with("file://") {
-                        let actor_repo_path = image.clone().replace("file://", "");
-
-                        // This is always an actor
-                        // But there may be a case when someone uses the wrong image
-                        let props = Helper::inspect_images(actor_repo_path.clone());
-
-                        if let ComponentClaims::Actor(props) = props {
-                            println!("result = {:#?}", &props);
-
-                            let actor_image_regex = Regex::new(r"/build/([^/]+)\.wasm").unwrap();
-                            let provider_image_regex = Regex::new(r"/build/([^/]+)\.par.gz").unwrap();
-                            let actor_repo_path: String = actor_image_regex.replace(&actor_repo_path, "").into();
-                            let actor_repo_path: String = provider_image_regex.replace(&actor_repo_path, "").into();
 
-                            self.state.add_item(component.name.clone(), actor_repo_path.clone(), props.module.clone(), component.clone(), ComponentClaims::Actor(props));
-
-                        } else {
-                            Logger::error_and_exit(format!("Oops, the provider image {} is not an actor", image));
-                        }
-
-                    } else {
-                        Logger::info(format!("Skipping non local component in: {}", image));
-                    }
-                }
-                Properties::Capability {
-                    properties
-                } => {
-                    let image = &properties.image;
-                    if image.starts_with("file://") {
-                        let capability_repo_path = image.clone().replace("file://", "");
-
-                        let props = Helper::inspect_images(capability_repo_path.clone());
+        Logger::warn(format!("The claims of {} changed after the rebuild:\n  - {}", component.name, changes.join("\n  - ")));
+        if !matches_properties(&component.properties, &claims) {
+            Logger::warn(format!("Keeping the old claims of {}, it no longer builds to the {} the manifest declares", component.name, if matches!(component.properties, Properties::Actor { .. }) { "actor" } else { "provider" }));
+            return;
+        }
 
-                        if let ComponentClaims::Provider(props) = props {
-                            println!("result = {:#?}", &props);
+        self.state.update_claims(path, claims.clone());
+        if old.get_id() != claims.get_id() && self.lock.is_some() {
+            self.record_session();
+        }
 
-                            let provider_image_regex = Regex::new(r"/build/([^/]+)\.par.gz").unwrap();
-                            let capability_repo_path: String = provider_image_regex.replace(&capability_repo_path, "").into();
+        let problems = Validator::new(&self.manifest).with_claims(self.state.get_claims()).validate_links();
+        if !problems.is_empty() {
+            Logger::warn(format!("The links of the manifest no longer match the claims of {}", component.name));
+            Progress::suspend(|| validation::print_problems(&problems, &self.arguments.config));
+        }
+    }
 
-                            self.state.add_item(component.name.clone(), capability_repo_path.clone(), props.service.clone(), component.clone(), ComponentClaims::Provider(props));
+    /// Builds every local component in parallel, with one progress spinner per build.
+    /// Returns whether every build succeeded
+    fn initial_build(&mut self) -> bool {
+        self.state.build_all()
+    }
 
-                        } else {
-                            Logger::error_and_exit(format!("Oops, the provider image {} is not a provider", image));
-                        }
+    /// Releases the lock and removes the session record of a session that put nothing into the lattice
+    fn abandon_session(&mut self) {
+        if let Some(lock) = self.lock.take() {
+            lock.release();
+        }
+        self.session.remove();
+    }
 
+    /// Takes the per lattice lock on the app, or exits when another session holds it
+    fn acquire_lock(&mut self) {
+        let lattice = self.get_lattice();
+        let app_name = &self.manifest.metadata.name;
 
-                    } else {
-                        Logger::info(format!("Skipping non local component in: {}", image));
-                    }
-                }
+        match SessionLock::acquire(&lattice, app_name, &self.arguments.config, self.arguments.take_over) {
+            Ok(lock) => {
+                self.lock = Some(lock);
+                self.update_cleanup_steps();
             }
-
+            Err(owner) => Logger::error_and_exit(format!(
+                "App {} on lattice {} is already being developed by the session with pid {} ({:?}).\n\
+                Use --read-only to attach without changing the lattice or --take-over to take it over",
+                app_name, lattice, owner.pid, owner.manifest_path
+            )),
         }
     }
 
+    /// Records the local components of this session in the state directory,
+    /// so they can be found by `kuwash clean` if kuwash dies without cleaning up
+    fn record_session(&mut self) {
+        self.session.components = self.state.get_components().iter()
+            .map(|(component, claims)| {
+                let (contract_id, link_name) = match &component.properties {
+                    Properties::Actor { .. } => (None, None),
+                    Properties::Capability { properties } => (Some(properties.contract.clone()), properties.link_name.clone()),
+                };
+                SessionComponent { name: component.name.clone(), id: claims.get_id(), contract_id, link_name }
+            })
+            .collect();
+        self.session.save();
+        self.update_cleanup_steps();
+    }
 
-    /// Validates that the actor has the capability to link to the provider
-    /// TODO: The traits present may be link defs to remote images
-    /// that are not managed in the state and will cause failures.
-    /// Find a way to identify only local images during inspection
-    /// Or add remote images to the state and
-    /// ignore them during file watching in listen_for_changes_and_redeploy function
-    fn validate_actor_claims(&self) {
-        let components = self.state.get_components();
-        for (component, claims) in components {
-
-
-            if let Properties::Actor { .. } = &component.properties {
-                let actor_claims = claims.get_actor_claims();
-
-                for t in &component.traits.clone().unwrap() {
-                    if let TraitProperty::Linkdef(props) = &t.properties {
-                        // get target from state by name
-                        println!("props = {:#?}", props.target.as_str());
-                        // println!("state = {:#?}", self.state);
-                        let (provider, provider_claims) = self.state.get_by_name(props.target.as_str());
-                        let provider_claims = provider_claims.get_provider_claims();
-
-                        println!("actor_claims = {:#?}", &actor_claims);
-                        println!("provider_claims = {:#?}", &provider_claims);
-                        if !(actor_claims.capabilities.contains(&provider_claims.capability_contract_id) || actor_claims.capabilities.contains(&provider_claims.name)) {
-                            Logger::error_and_exit(format!("Actor ({}) does not have the capability {} to link to provider {}", component.name, provider_claims.capability_contract_id, provider.name));
-                        }
-                        // check for target contract_id
-
-                        // check for target contract_id
-                        // check if component image has claims
-                        // if it doesn't fail and exist
-                    }
-                }
-            }
+    /// Hands the signal handler what to clean up so far: the steps grow
+    /// as the session takes the lock, records itself and puts versions
+    fn update_cleanup_steps(&self) {
+        if let Some(lock) = &self.lock {
+            Shutdown::set_steps(cleanup_steps(&self.manifest, &self.session, lock, self.get_exit_policy()));
         }
+    }
 
+    /// Sets up a local state of the application
+    /// capturing the components in the manifest and
+    /// the claims in their images. All stored in self.state
+    fn setup_image_maping(&mut self) {
+        self.state = ComponentData::from_manifest(&self.manifest);
     }
+
+
     /// This harnesses the full power of the wash api
     pub fn start(&mut self) {
 
@@ -299,13 +259,19 @@ impl DevCommand {
     }
 
     pub fn new(manifest: Manifest, arguments: &DevArgs) -> DevCommand {
+        let manifest_name = manifest.metadata.name.clone();
+        let hosts = Helper::get_hosts();
+        let lattice = Helper::get_lattice(&hosts);
         DevCommand {
             actors: Vec::new(),
             manifest,
             arguments: arguments.clone(),
-            hosts: Helper::get_hosts(),
+            hosts,
             inventory: Helper::get_host_inventory(),
-            state: ComponentData::new()
+            state: ComponentData::new(),
+            session: Session::new(manifest_name, lattice, arguments.config.clone(), Vec::new()),
+            lock: None,
+            config: ProjectConfig::load(&arguments.config)
         }
     }
 
@@ -316,10 +282,6 @@ impl DevCommand {
 }
 
 impl DevCommand {
-    pub fn get_manifest_path(&self) -> PathBuf {
-        self.arguments.config.clone()
-    }
-
     #[allow(dead_code)]
     pub fn get_actors(&mut self) -> ActorDescriptions {
         self.inventory.iter().flat_map(|item| item.actors.clone()).collect()
@@ -338,6 +300,18 @@ impl DevCommand {
             .collect::<Vec<_>>()
     }
 
+    /// The exit policy from the command line, the project config or the default
+    pub fn get_exit_policy(&self) -> ExitPolicy {
+        self.arguments.on_exit
+            .or(self.config.on_exit)
+            .unwrap_or_default()
+    }
+
+    /// The lattice prefix reported by the running hosts
+    pub fn get_lattice(&self) -> String {
+        Helper::get_lattice(&self.hosts)
+    }
+
     // allow dead code
     #[allow(dead_code)]
     pub fn get_host(&mut self) -> Host {
@@ -346,25 +320,132 @@ impl DevCommand {
 
     #[allow(dead_code)]
     pub fn cleanup(&self) {
-        Helper::undeploy_app(&self.manifest);
-        Helper::delete_app(&self.manifest);
+        if let Some(lock) = &self.lock {
+            for step in cleanup_steps(&self.manifest, &self.session, lock, self.get_exit_policy()) {
+                (step.run)();
+            }
+        }
     }
 
-    pub fn deploy(&self) {
-        Helper::put_app(self.get_manifest_path().canonicalize().unwrap().to_str().unwrap());
+    pub fn deploy(&mut self) {
+        let app_name = self.manifest.metadata.name.clone();
+        let mut task = Progress::spinner(format!("Putting app {}", app_name));
+        Helper::put_manifest(&self.manifest);
+        self.session.add_version(self.manifest.version());
+        self.update_cleanup_steps();
+
+        task.set_message(format!("Deploying app {}", app_name));
         Helper::deploy_app(&self.manifest);
+        task.finish(format!("Deployed app {}:{}", app_name, self.manifest.version()));
+
+        self.state.wait_until_running();
     }
 
     #[allow(dead_code)]
-    fn cleanup_and_deploy(&self) {
+    fn cleanup_and_deploy(&mut self) {
         self.cleanup();
         self.deploy();
     }
 }
 
+/// Rebuilds a changed component and replaces the instance running the old build.
+/// Actors are stopped so that wadm starts the new build, providers are reloaded with
+/// their config and links, see `ProviderReload`. Returns the claims of the new build, None when it failed
+fn rebuild(path: &str, manifest: &Manifest, state: &ComponentData, lock: &Option<SessionLock>) -> Option<(String, ComponentClaims)> {
+    let (component, claims) = state.get_by_path(path);
+    let (image, built) = match &component.properties {
+        Properties::Actor { properties } => {
+            Logger::info(format!("Rebuilding actor: {}", properties.image));
+            (&properties.image, Helper::build_actor(path))
+        }
+        Properties::Capability { properties } => {
+            Logger::info(format!("Rebuilding provider: {}", properties.image));
+            (&properties.image, Helper::build_provider(properties))
+        }
+    };
+    if !built {
+        return None;
+    }
+
+    let artifact = Helper::local_image_path(image)?;
+    let rebuilt = Helper::try_inspect_image(&artifact, &schema::component_id(manifest, &component));
+
+    // The running instance has the old ID, even when the rebuild changed the public key
+    if owns_lattice(lock) {
+        match (&claims, &rebuilt) {
+            (ComponentClaims::Provider(old), Ok(ComponentClaims::Provider(new))) => {
+                if let Some(reload) = ProviderReload::new(manifest, state, &component) {
+                    reload.run(old, new);
+                }
+            }
+            (ComponentClaims::Provider(old), _) => {
+                let link_name = match &component.properties {
+                    Properties::Capability { properties } => properties.link_name.clone(),
+                    Properties::Actor { .. } => None,
+                };
+                Helper::stop_provider(&old.public_key, link_name.as_deref().unwrap_or("default"), &old.contract_id, None);
+            }
+            (ComponentClaims::Actor(claims), _) => Helper::stop_actor(&claims.public_key),
+            (ComponentClaims::Component(component), _) => Helper::stop_actor(&component.id),
+        }
+    }
+
+    match rebuilt {
+        Ok(claims) => Some((path.to_string(), claims)),
+        Err(error) => {
+            Logger::warn(format!("Could not read the claims of the rebuilt {}: {}", component.name, error.trim()));
+            None
+        }
+    }
+}
+
 fn cleanup(manifest: &Manifest) {
-    Helper::undeploy_app(&manifest);
-    Helper::delete_app(&manifest);
+    Helper::undeploy_app(manifest);
+    Helper::delete_app(manifest);
+}
+
+/// Whether this session may change the lattice: it holds the app lock
+/// and was not taken over by another session
+fn owns_lattice(lock: &Option<SessionLock>) -> bool {
+    match lock {
+        Some(lock) if lock.is_held() => true,
+        Some(lock) => {
+            Logger::info(format!("App {} was taken over by another session, leaving the lattice untouched", lock.app_name));
+            false
+        }
+        None => false,
+    }
+}
+
+/// The steps run when dev mode stops, depending on the exit policy.
+/// Only the app versions this session put are deleted, and nothing is
+/// removed if the app was taken over by another session.
+/// The session record is only removed when everything else was cleaned up,
+/// so `kuwash clean` can finish the job otherwise
+fn cleanup_steps(manifest: &Manifest, session: &Session, lock: &SessionLock, policy: ExitPolicy) -> Vec<CleanupStep> {
+    let app_name = manifest.metadata.name.clone();
+    let mut steps = Vec::new();
+
+    // Nothing to undeploy before the session put a version
+    if policy != ExitPolicy::Keep && !session.versions.is_empty() {
+        let lock = Some(lock.clone());
+        let name = app_name.clone();
+        steps.push(CleanupStep::new(format!("Undeploy app {}", app_name), move || !owns_lattice(&lock) || Helper::undeploy_app_by_name(&name)));
+    }
+
+    if policy == ExitPolicy::Delete {
+        for version in &session.versions {
+            let lock = Some(lock.clone());
+            let (name, version) = (app_name.clone(), version.clone());
+            steps.push(CleanupStep::new(format!("Delete app {}:{}", app_name, version), move || !owns_lattice(&lock) || Helper::delete_app_version(&name, &version)));
+        }
+    }
+
+    let release_lock = lock.clone();
+    let session = session.clone();
+    steps.push(CleanupStep::new(format!("Release lock on app {}", app_name), move || release_lock.release()));
+    steps.push(CleanupStep::after_success(format!("Remove session record {:?}", session.path()), move || session.remove()));
+    steps
 }
 
 #[allow(dead_code)]
@@ -0,0 +1,44 @@
+use wadm::model::Manifest;
+use crate::arguments::{DiffArgs, OutputFormat};
+use crate::diff::ManifestDiff;
+use crate::helper::Helper;
+use crate::logger::Logger;
+
+/// Shows what deploying the local manifest would change in wadm
+pub struct DiffCommand {
+    pub manifest: Manifest,
+    pub arguments: DiffArgs,
+}
+
+impl DiffCommand {
+    pub fn new(manifest: Manifest, arguments: &DiffArgs) -> DiffCommand {
+        DiffCommand {
+            manifest,
+            arguments: arguments.clone(),
+        }
+    }
+
+    pub fn start(&self) {
+        Logger::set_quiet(true);
+
+        let deployed = deployed_manifest(&self.manifest.metadata.name);
+        let diff = ManifestDiff::between(deployed.as_ref(), &self.manifest);
+
+        match self.arguments.output {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&diff).unwrap()),
+            OutputFormat::Table => {
+                match &deployed {
+                    Some(deployed) => println!("App {}: deployed {} → local {}\n", self.manifest.metadata.name, deployed.version(), self.manifest.version()),
+                    None => println!("App {} is not deployed, every component would be added\n", self.manifest.metadata.name),
+                }
+                diff.print();
+            }
+        }
+    }
+}
+
+/// The manifest of the version wadm has deployed, None when the app is not deployed
+pub fn deployed_manifest(app_name: &str) -> Option<Manifest> {
+    let version = Helper::get_app(app_name)?.deployed_version?;
+    Helper::get_app_manifest(app_name, Some(&version))
+}
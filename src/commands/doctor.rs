@@ -0,0 +1,294 @@
+use std::fs;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::process::{self, exit};
+use std::time::Duration;
+use colored::*;
+use regex::Regex;
+use wadm::model::{Manifest, Properties};
+use crate::arguments::DoctorArgs;
+use crate::helper::{GetHostCommandOutput, Helper};
+use crate::provider_archive::ProviderArchive;
+
+/// Oldest wash release whose `app` and `get` subcommands kuwash understands
+const MIN_WASH_VERSION: (u64, u64, u64) = (0, 21, 0);
+/// First wash release kuwash has not been tested against
+const MAX_WASH_VERSION: (u64, u64, u64) = (0, 25, 0);
+const NATS_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Outcome of a single diagnostic
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// How to fix a warning or failure
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &str, detail: String) -> Check {
+        Check { name: name.to_string(), status: CheckStatus::Pass, detail, hint: None }
+    }
+
+    fn warn(name: &str, detail: String, hint: &str) -> Check {
+        Check { name: name.to_string(), status: CheckStatus::Warn, detail, hint: Some(hint.to_string()) }
+    }
+
+    fn fail(name: &str, detail: String, hint: &str) -> Check {
+        Check { name: name.to_string(), status: CheckStatus::Fail, detail, hint: Some(hint.to_string()) }
+    }
+
+    fn print(&self) {
+        let status = match self.status {
+            CheckStatus::Pass => "pass".green().bold(),
+            CheckStatus::Warn => "warn".yellow().bold(),
+            CheckStatus::Fail => "fail".red().bold(),
+        };
+        println!("[{}] {:<12} {}", status, self.name, self.detail);
+        if let Some(hint) = &self.hint {
+            println!("       {:<12} {} {}", "", "hint:".bold(), hint);
+        }
+    }
+}
+
+/// Diagnoses the tools, the lattice and the project kuwash depends on
+pub struct DoctorCommand {
+    pub arguments: DoctorArgs,
+}
+
+impl DoctorCommand {
+    pub fn new(arguments: &DoctorArgs) -> DoctorCommand {
+        DoctorCommand {
+            arguments: arguments.clone(),
+        }
+    }
+
+    pub fn start(&self) {
+        let manifest = self.arguments.config.as_ref().map(|path| (path, Helper::get_manifest_from_wadm_config(path)));
+        let local_paths: Vec<(bool, String)> = match &manifest {
+            Some((_, Ok(manifest))) => local_artifacts(manifest),
+            _ => Vec::new(),
+        };
+        // Cargo providers without a Makefile are packaged natively, only the others need make
+        let has_make_providers = local_paths.iter()
+            .any(|(provider, path)| *provider && !ProviderArchive::supports(Path::new(&Helper::local_repo_path(path))));
+
+        let wash = check_wash();
+        let wash_found = wash.status != CheckStatus::Fail;
+        let mut checks = vec![
+            wash,
+            check_tool("cargo", true, "install Rust with rustup from https://rustup.rs"),
+            check_tool("make", has_make_providers, "install make, it builds the local providers of the manifest that kuwash does not package natively"),
+            check_wasm_targets(),
+            check_nats(),
+        ];
+
+        if wash_found {
+            checks.push(check_hosts());
+            checks.push(check_wadm());
+        }
+
+        checks.push(check_inotify(&local_paths));
+
+        match &manifest {
+            Some((path, Ok(_))) => {
+                checks.push(Check::pass("manifest", format!("{:?} parsed", path)));
+                checks.extend(local_paths.iter().map(|(_, path)| check_writable(path)));
+            }
+            Some((path, Err(error))) => {
+                checks.push(Check::fail("manifest", format!("{:?}: {}", path, error), "fix the YAML of the manifest"));
+            }
+            None => {}
+        }
+
+        for check in &checks {
+            check.print();
+        }
+
+        let count = |status| checks.iter().filter(|check| check.status == status).count();
+        println!("\n{} passed, {} warning(s), {} failed", count(CheckStatus::Pass), count(CheckStatus::Warn), count(CheckStatus::Fail));
+
+        if count(CheckStatus::Fail) > 0 {
+            exit(1);
+        }
+    }
+}
+
+/// Local artifact paths of the manifest, flagged when they belong to a provider
+fn local_artifacts(manifest: &Manifest) -> Vec<(bool, String)> {
+    manifest.spec.components.iter()
+        .filter_map(|component| match &component.properties {
+            Properties::Actor { properties } => Helper::local_image_path(&properties.image).map(|path| (false, path)),
+            Properties::Capability { properties } => Helper::local_image_path(&properties.image).map(|path| (true, path)),
+        })
+        .collect()
+}
+
+fn check_wash() -> Check {
+    let path = match Helper::find_executable("wash") {
+        Some(path) => path,
+        None => return Check::fail("wash", "not found in PATH".to_string(), "install wash, see https://wasmcloud.com/docs/installation"),
+    };
+
+    let output = match process::Command::new(&path).arg("--version").output() {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).to_string(),
+        _ => return Check::fail("wash", format!("{:?} does not run", path), "reinstall wash"),
+    };
+
+    let supported = format!("{} to {}", format_version(MIN_WASH_VERSION), format_version(MAX_WASH_VERSION));
+    match parse_version(&output) {
+        Some(version) if version < MIN_WASH_VERSION || version >= MAX_WASH_VERSION => {
+            Check::warn("wash", format!("{} at {:?} is not supported", format_version(version), path), &format!("install a wash release from {} (exclusive)", supported))
+        }
+        Some(version) => Check::pass("wash", format!("{} at {:?}", format_version(version), path)),
+        None => Check::warn("wash", format!("unknown version {:?}", output.trim()), &format!("kuwash supports wash {} (exclusive)", supported)),
+    }
+}
+
+fn check_tool(name: &str, required: bool, hint: &str) -> Check {
+    match Helper::find_executable(name) {
+        Some(path) => Check::pass(name, format!("found at {:?}", path)),
+        None if required => Check::fail(name, "not found in PATH".to_string(), hint),
+        None => Check::warn(name, "not found in PATH".to_string(), hint),
+    }
+}
+
+fn check_wasm_targets() -> Check {
+    let output = match process::Command::new("rustup").args(["target", "list", "--installed"]).output() {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).to_string(),
+        _ => return Check::warn("wasm32", "rustup not found, cannot list the installed targets".to_string(), "install Rust with rustup from https://rustup.rs"),
+    };
+
+    let installed: Vec<&str> = output.lines().map(|line| line.trim()).filter(|line| line.starts_with("wasm32")).collect();
+    let mut missing = Vec::new();
+    if !installed.contains(&"wasm32-unknown-unknown") {
+        missing.push("wasm32-unknown-unknown");
+    }
+    if !installed.iter().any(|target| *target == "wasm32-wasi" || *target == "wasm32-wasip1") {
+        missing.push("wasm32-wasip1");
+    }
+
+    if missing.is_empty() {
+        Check::pass("wasm32", installed.join(", "))
+    } else {
+        Check::warn("wasm32", format!("missing {}", missing.join(", ")), &format!("rustup target add {}", missing.join(" ")))
+    }
+}
+
+/// The control interface address wash uses, from the same environment variables
+fn check_nats() -> Check {
+    let host = std::env::var("WASMCLOUD_CTL_HOST").unwrap_or("127.0.0.1".to_string());
+    let port = std::env::var("WASMCLOUD_CTL_PORT").unwrap_or("4222".to_string());
+    let address = format!("{}:{}", host, port);
+
+    let reachable = address.to_socket_addrs()
+        .map(|mut addresses| addresses.any(|address| TcpStream::connect_timeout(&address, NATS_TIMEOUT).is_ok()))
+        .unwrap_or(false);
+
+    if reachable {
+        Check::pass("nats", format!("reachable at {}", address))
+    } else {
+        Check::fail("nats", format!("nothing listens on {}", address), "start a lattice with `wash up`, or set WASMCLOUD_CTL_HOST and WASMCLOUD_CTL_PORT")
+    }
+}
+
+fn check_hosts() -> Check {
+    let output = match process::Command::new("wash").args(["get", "hosts", "-o", "json"]).output() {
+        Ok(output) => output,
+        Err(error) => return Check::fail("hosts", error.to_string(), "reinstall wash"),
+    };
+
+    let result: Option<GetHostCommandOutput> = serde_json::from_slice(&output.stdout).ok();
+    match result {
+        Some(GetHostCommandOutput { success: true, hosts: Some(hosts), .. }) if !hosts.is_empty() => {
+            Check::pass("hosts", format!("{} host(s) on lattice {}", hosts.len(), Helper::get_lattice(&hosts)))
+        }
+        Some(GetHostCommandOutput { success: true, .. }) => {
+            Check::fail("hosts", "no hosts are running in the lattice".to_string(), "start a host with `wash up`")
+        }
+        Some(GetHostCommandOutput { error, .. }) => {
+            Check::fail("hosts", error.unwrap_or("wash get hosts failed".to_string()), "start a lattice with `wash up`")
+        }
+        None => {
+            Check::fail("hosts", String::from_utf8_lossy(&output.stderr).trim().to_string(), "start a lattice with `wash up`")
+        }
+    }
+}
+
+fn check_wadm() -> Check {
+    match process::Command::new("wash").args(["app", "list", "-o", "json"]).output() {
+        Ok(output) if output.status.success() => Check::pass("wadm", "answers `wash app list`".to_string()),
+        Ok(output) => {
+            Check::fail("wadm", String::from_utf8_lossy(&output.stderr).trim().to_string(), "start wadm with `wash up`, it is required to deploy manifests")
+        }
+        Err(error) => Check::fail("wadm", error.to_string(), "reinstall wash"),
+    }
+}
+
+/// Dev mode watches the `src` directory of every local component, one inotify watch per directory
+fn check_inotify(local_paths: &[(bool, String)]) -> Check {
+    let limit = match fs::read_to_string("/proc/sys/fs/inotify/max_user_watches") {
+        Ok(limit) => limit.trim().parse::<usize>().unwrap_or(0),
+        Err(_) => return Check::pass("inotify", "not on Linux, nothing to check".to_string()),
+    };
+
+    let needed: usize = local_paths.iter()
+        .map(|(_, path)| count_directories(&Path::new(&Helper::local_repo_path(path)).join("src")))
+        .sum();
+
+    let hint = "raise the limit, e.g. `sudo sysctl fs.inotify.max_user_watches=524288`";
+    if needed > limit {
+        Check::fail("inotify", format!("{} watches needed, max_user_watches is {}", needed, limit), hint)
+    } else if needed * 2 > limit {
+        Check::warn("inotify", format!("{} watches needed, close to max_user_watches of {}", needed, limit), hint)
+    } else {
+        Check::pass("inotify", format!("{} watches needed, max_user_watches is {}", needed, limit))
+    }
+}
+
+fn count_directories(path: &Path) -> usize {
+    match fs::read_dir(path) {
+        Ok(entries) => 1 + entries.filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false))
+            .map(|entry| count_directories(&entry.path()))
+            .sum::<usize>(),
+        Err(_) => 0,
+    }
+}
+
+/// Builds write the artifact of a `file://` image, so its directory or closest existing ancestor must be writable
+fn check_writable(artifact: &str) -> Check {
+    let name = "file://";
+    let mut directory = Path::new(artifact).parent().map(Path::to_path_buf).unwrap_or_default();
+    while !directory.exists() {
+        match directory.parent() {
+            Some(parent) => directory = parent.to_path_buf(),
+            None => return Check::fail(name, format!("{} has no existing parent directory", artifact), "fix the image path in the manifest"),
+        }
+    }
+
+    let probe: PathBuf = directory.join(format!(".kuwash-doctor-{}", process::id()));
+    match fs::write(&probe, b"") {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            Check::pass(name, format!("{} is writable", artifact))
+        }
+        Err(error) => Check::fail(name, format!("cannot write to {:?}: {}", directory, error), "fix the directory permissions or point the image at a writable build directory"),
+    }
+}
+
+fn parse_version(output: &str) -> Option<(u64, u64, u64)> {
+    let captures = Regex::new(r"(\d+)\.(\d+)\.(\d+)").unwrap().captures(output)?;
+    Some((captures[1].parse().ok()?, captures[2].parse().ok()?, captures[3].parse().ok()?))
+}
+
+fn format_version(version: (u64, u64, u64)) -> String {
+    format!("{}.{}.{}", version.0, version.1, version.2)
+}
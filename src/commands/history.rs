@@ -0,0 +1,98 @@
+use serde::Serialize;
+use wadm::model::Properties;
+use crate::arguments::{HistoryArgs, OutputFormat};
+use crate::helper::Helper;
+use crate::history::{AppHistory, ImageRecord};
+use crate::logger::Logger;
+use crate::table::{human_age, Table};
+
+/// A version of an app as wadm stores it, with what kuwash recorded when putting it
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub version: String,
+    pub deployed: bool,
+    /// Unix timestamp of the put, None when the version was not put by kuwash
+    pub put_at: Option<u64>,
+    pub images: Vec<ImageRecord>,
+}
+
+/// Lists the stored versions of an app, oldest first
+pub struct HistoryCommand {
+    pub arguments: HistoryArgs,
+}
+
+impl HistoryCommand {
+    pub fn new(arguments: &HistoryArgs) -> HistoryCommand {
+        HistoryCommand {
+            arguments: arguments.clone(),
+        }
+    }
+
+    pub fn start(&self) {
+        let app_name = &self.arguments.app_name;
+        let versions = Helper::get_app_versions(app_name);
+        if versions.is_empty() {
+            Logger::error_and_exit(format!("App {} has no versions stored in wadm", app_name));
+        }
+
+        let history = AppHistory::load(app_name);
+        let entries: Vec<HistoryEntry> = versions.iter()
+            .map(|version| {
+                let record = history.get(&version.version);
+                HistoryEntry {
+                    version: version.version.clone(),
+                    deployed: version.deployed,
+                    put_at: record.map(|record| record.put_at),
+                    images: stored_images(app_name, &version.version, record.map(|record| record.images.as_slice()).unwrap_or_default()),
+                }
+            })
+            .collect();
+
+        match self.arguments.output {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&entries).unwrap()),
+            OutputFormat::Table => print_table(&entries),
+        }
+    }
+}
+
+/// The images of a stored version as wadm has them, with the digests kuwash recorded for its local artifacts
+fn stored_images(app_name: &str, version: &str, recorded: &[ImageRecord]) -> Vec<ImageRecord> {
+    let manifest = match Helper::get_app_manifest(app_name, Some(version)) {
+        Some(manifest) => manifest,
+        None => return recorded.to_vec(),
+    };
+
+    manifest.spec.components.iter()
+        .map(|component| {
+            let image = match &component.properties {
+                Properties::Actor { properties } => properties.image.clone(),
+                Properties::Capability { properties } => properties.image.clone(),
+            };
+            let sha256 = recorded.iter()
+                .find(|record| record.component == component.name && record.image == image)
+                .and_then(|record| record.sha256.clone());
+            ImageRecord { component: component.name.clone(), image, sha256 }
+        })
+        .collect()
+}
+
+fn print_table(entries: &[HistoryEntry]) {
+    let mut table = Table::new(&["VERSION", "DEPLOYED", "PUT", "COMPONENT", "IMAGE"]);
+    for entry in entries {
+        let mut columns = vec![
+            entry.version.clone(),
+            if entry.deployed { "yes".to_string() } else { String::new() },
+            entry.put_at.map(human_age).unwrap_or("-".to_string()),
+        ];
+
+        if entry.images.is_empty() {
+            table.add_row([columns.clone(), vec!["-".to_string(), "-".to_string()]].concat());
+        }
+        for image in &entry.images {
+            table.add_row([columns.clone(), vec![image.component.clone(), image.image.clone()]].concat());
+            // Only the first image of a version repeats its details
+            columns = vec![String::new(); 3];
+        }
+    }
+    table.print();
+}
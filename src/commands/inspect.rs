@@ -0,0 +1,187 @@
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use colored::*;
+use serde::Serialize;
+use wadm::model::{Component, Manifest, Properties};
+use crate::arguments::{InspectArgs, OutputFormat};
+use crate::claims::ComponentClaims;
+use crate::helper::Helper;
+use crate::logger::Logger;
+use crate::schema;
+use crate::table::{human_age, Table};
+
+/// Claims and artifact details of a local component
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentReport {
+    pub name: String,
+    pub kind: String,
+    pub artifact: String,
+    /// Module key of an actor or service key of a provider, None when the artifact is missing
+    pub public_key: Option<String>,
+    pub issuer: Option<String>,
+    /// Capabilities of an actor or the contract ID of a provider
+    pub capabilities: Vec<String>,
+    pub revision: Option<String>,
+    pub version: Option<String>,
+    pub size: Option<u64>,
+    /// Unix timestamp of the artifact's last modification
+    pub modified: Option<u64>,
+    /// The artifact is missing or older than the sources of the component
+    pub stale: bool,
+    /// Why the claims of the artifact could not be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Consolidated claims report for every local component of the manifest
+pub struct InspectCommand {
+    pub manifest: Manifest,
+    pub arguments: InspectArgs,
+}
+
+impl InspectCommand {
+    pub fn new(manifest: Manifest, arguments: &InspectArgs) -> InspectCommand {
+        InspectCommand {
+            manifest,
+            arguments: arguments.clone(),
+        }
+    }
+
+    pub fn start(&self) {
+        Logger::set_quiet(true);
+
+        let reports: Vec<ComponentReport> = self.manifest.spec.components.iter()
+            .filter_map(|component| component_report(component, &schema::component_id(&self.manifest, component)))
+            .collect();
+
+        match self.arguments.output {
+            OutputFormat::Json => {
+                println!("{}", serde_json::to_string_pretty(&reports).unwrap());
+            }
+            OutputFormat::Table => {
+                if reports.is_empty() {
+                    println!("No local actors or providers found in {:?}", self.arguments.config);
+                    return;
+                }
+
+                let mut table = Table::new(&["COMPONENT", "KIND", "PUBLIC KEY", "ISSUER", "CAPABILITIES", "REV", "VERSION", "SIZE", "MODIFIED"]);
+                for report in &reports {
+                    table.add_row(vec![
+                        report.name.clone(),
+                        report.kind.clone(),
+                        report.public_key.clone().unwrap_or("-".to_string()),
+                        report.issuer.clone().unwrap_or("-".to_string()),
+                        report.capabilities.join(","),
+                        report.revision.clone().unwrap_or("-".to_string()),
+                        report.version.clone().unwrap_or("-".to_string()),
+                        report.size.map(human_size).unwrap_or("-".to_string()),
+                        report.modified.map(human_age).unwrap_or("-".to_string()),
+                    ]);
+                }
+                table.print();
+
+                let stale: Vec<&ComponentReport> = reports.iter().filter(|report| report.stale).collect();
+                if !stale.is_empty() || reports.iter().any(|report| report.error.is_some()) {
+                    println!();
+                }
+                for report in stale {
+                    let reason = if report.modified.is_some() { "artifact is older than its sources, rebuild it" } else { "artifact has not been built" };
+                    println!("{} {}: {} ({})", "stale:".yellow().bold(), report.name, reason, report.artifact);
+                }
+                for report in reports.iter().filter(|report| report.error.is_some()) {
+                    println!("{} {}: {}", "error:".red().bold(), report.name, report.error.as_deref().unwrap_or_default());
+                }
+            }
+        }
+    }
+}
+
+/// None for remote components
+fn component_report(component: &Component, id: &str) -> Option<ComponentReport> {
+    let (kind, image) = match &component.properties {
+        Properties::Actor { properties } => ("actor", &properties.image),
+        Properties::Capability { properties } => ("capability", &properties.image),
+    };
+    let artifact = Helper::local_image_path(image)?;
+
+    let mut report = ComponentReport {
+        name: component.name.clone(),
+        kind: kind.to_string(),
+        artifact: artifact.clone(),
+        public_key: None,
+        issuer: None,
+        capabilities: Vec::new(),
+        revision: None,
+        version: None,
+        size: None,
+        modified: None,
+        stale: true,
+        error: None,
+    };
+
+    let metadata = match fs::metadata(&artifact) {
+        Ok(metadata) => metadata,
+        Err(_) => return Some(report),
+    };
+    let modified = metadata.modified().ok();
+    report.size = Some(metadata.len());
+    report.modified = modified.and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs());
+
+    let sources_modified = newest_modification(&Path::new(&Helper::local_repo_path(&artifact)).join("src"));
+    report.stale = match (modified, sources_modified) {
+        (Some(artifact), Some(sources)) => sources > artifact,
+        _ => false,
+    };
+
+    let claims = match Helper::try_inspect_image(&artifact, id) {
+        Ok(claims) => claims,
+        Err(error) => {
+            report.error = Some(error.trim().to_string());
+            return Some(report);
+        }
+    };
+    match claims {
+        ComponentClaims::Actor(claims) => {
+            report.public_key = Some(claims.public_key);
+            report.issuer = Some(claims.issuer);
+            report.capabilities = claims.capabilities;
+            report.revision = claims.revision.map(|revision| revision.to_string());
+            report.version = claims.version;
+        }
+        ComponentClaims::Provider(claims) => {
+            report.public_key = Some(claims.public_key);
+            report.issuer = Some(claims.issuer);
+            report.capabilities = vec![claims.contract_id];
+            report.revision = claims.revision.map(|revision| revision.to_string());
+            report.version = claims.version;
+        }
+        ComponentClaims::Component(component) => {
+            report.kind = "component".to_string();
+            report.public_key = Some(component.id);
+        }
+    }
+
+    Some(report)
+}
+
+/// Latest modification time of any file below `path`
+fn newest_modification(path: &Path) -> Option<SystemTime> {
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_dir() {
+        return metadata.modified().ok();
+    }
+
+    fs::read_dir(path).ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| newest_modification(&entry.path()))
+        .max()
+}
+
+fn human_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 => format!("{:.1} MiB", b as f64 / (1024.0 * 1024.0)),
+        b if b >= 1024 => format!("{:.1} KiB", b as f64 / 1024.0),
+        b => format!("{} B", b),
+    }
+}
@@ -0,0 +1,120 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use wadm::model::{Manifest, Properties};
+use crate::arguments::ReleaseArgs;
+use crate::config::ProjectConfig;
+use crate::helper::Helper;
+use crate::logger::Logger;
+use crate::progress::Progress;
+use crate::schema;
+
+/// Turns a dev manifest into one for shared environments: every local (`file://`)
+/// component is built in release mode and pushed to the registry, and its image is
+/// replaced by the pushed reference pinned to its digest, e.g.
+/// `localhost:5000/kukuana/echo:0.1.0@sha256:...`. Remote images are kept as they are.
+pub struct ReleaseCommand {
+    pub manifest: Manifest,
+    pub arguments: ReleaseArgs,
+}
+
+impl ReleaseCommand {
+    pub fn new(manifest: Manifest, arguments: &ReleaseArgs) -> ReleaseCommand {
+        ReleaseCommand {
+            manifest,
+            arguments: arguments.clone(),
+        }
+    }
+
+    pub fn start(&self) {
+        let config = ProjectConfig::load(&self.arguments.config).registry.unwrap_or_default();
+        let registry = match &self.arguments.registry {
+            Some(registry) => registry.clone(),
+            None if !config.url.is_empty() => config.url.clone(),
+            None => Logger::error_and_exit("No registry to push to, set `registry.url` in kuwash.yaml or pass --registry".to_string()),
+        };
+        let registry = registry.trim_end_matches('/');
+        let insecure = self.arguments.insecure || config.insecure;
+        // Components whose claims carry no version are tagged with the manifest's
+        if self.manifest.version().trim().is_empty() {
+            Logger::error_and_exit(format!("{:?} has no `version` annotation, add one to tag the released components with", self.arguments.config));
+        }
+
+        let mut manifest = self.manifest.clone();
+        let mut released = 0;
+        for component in manifest.spec.components.iter_mut() {
+            let id = schema::component_id(&self.manifest, component);
+            let image = match &component.properties {
+                Properties::Actor { properties } => &properties.image,
+                Properties::Capability { properties } => &properties.image,
+            };
+            let artifact = match Helper::local_image_path(image) {
+                Some(artifact) => artifact,
+                None => continue,
+            };
+
+            let mut task = Progress::spinner(format!("Building {} in release mode", component.name));
+            if !Helper::build_release(&component.properties) || !Path::new(&artifact).exists() {
+                task.fail(format!("Could not build {} to {}", component.name, artifact));
+                exit(1);
+            }
+
+            let claims = Helper::try_inspect_image(&artifact, &id).unwrap_or_else(|error| {
+                task.fail(format!("Could not read the claims of {}", artifact));
+                Logger::error_and_exit(error)
+            });
+            let tag = claims.version().filter(|version| !version.is_empty()).unwrap_or(self.manifest.version());
+            let reference = format!("{}/{}:{}", registry, repository_name(&component.name), sanitize_tag(tag));
+
+            task.set_message(format!("Pushing {} to {}", component.name, reference));
+            let digest = Helper::push_artifact(&reference, &artifact, insecure).unwrap_or_else(|error| {
+                task.fail(format!("Could not push {} to {}", component.name, reference));
+                Logger::error_and_exit(error)
+            });
+
+            let image = match &mut component.properties {
+                Properties::Actor { properties } => &mut properties.image,
+                Properties::Capability { properties } => &mut properties.image,
+            };
+            *image = format!("{}@{}", reference, digest);
+            task.finish(format!("Released {} as {}", component.name, image));
+            released += 1;
+        }
+
+        if released == 0 {
+            Logger::info(format!("No local actors or providers found in {:?}, nothing to release", self.arguments.config));
+            return;
+        }
+
+        let path = self.arguments.out.clone().unwrap_or_else(|| release_path(&self.arguments.config));
+        let written = schema::to_yaml(&manifest)
+            .and_then(|yaml| fs::write(&path, yaml).map_err(|error| error.to_string()));
+        match written {
+            Ok(_) => println!("Wrote the release manifest of {}:{} to {:?}", manifest.metadata.name, manifest.version(), path),
+            Err(error) => Logger::error_and_exit(format!("Could not write the release manifest to {:?}: {}", path, error)),
+        }
+    }
+}
+
+/// `<manifest>-release.yaml` next to the manifest
+fn release_path(manifest_path: &Path) -> PathBuf {
+    let stem = manifest_path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or("wadm".to_string());
+    let extension = manifest_path.extension().map(|extension| extension.to_string_lossy().to_string()).unwrap_or("yaml".to_string());
+    manifest_path.with_file_name(format!("{}-release.{}", stem, extension))
+}
+
+/// OCI repository names are lowercase letters, digits and separators
+fn repository_name(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') { c } else { '-' })
+        .collect()
+}
+
+/// OCI tags are letters, digits, `_`, `.` and `-`, e.g. `v0.1.0+build` becomes `v0.1.0-build`
+fn sanitize_tag(tag: &str) -> String {
+    tag.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') { c } else { '-' })
+        .take(128)
+        .collect()
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use serde_json::Value;
+use wadm::model::{CapabilityProperties, Component, Manifest, Properties, Trait, TraitProperty};
+use crate::claims::ProviderClaims;
+use crate::commands::component_data::ComponentData;
+use crate::helper::{Helper, LinkDefinition, LovalHostInventory};
+use crate::progress::Progress;
+use crate::schema::link_target;
+
+/// How long to wait for a reloaded provider and its links to show up in the lattice
+const RELOAD_TIMEOUT: Duration = Duration::from_secs(60);
+const RELOAD_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Replaces the running instance of a rebuilt provider with its new build.
+///
+/// A provider instance is identified by its service key and link name, so only that
+/// instance is stopped, on every host it runs on. The new `.par.gz` is started on the
+/// same hosts with the manifest's config, and every link between an actor and the provider
+/// is put again since the links of the old instance may point at an old service key.
+pub struct ProviderReload<'a> {
+    manifest: &'a Manifest,
+    state: &'a ComponentData,
+    component: &'a Component,
+    properties: &'a CapabilityProperties,
+}
+
+impl<'a> ProviderReload<'a> {
+    /// None when the component is not a capability
+    pub fn new(manifest: &'a Manifest, state: &'a ComponentData, component: &'a Component) -> Option<ProviderReload<'a>> {
+        match &component.properties {
+            Properties::Capability { properties } => Some(ProviderReload { manifest, state, component, properties }),
+            Properties::Actor { .. } => None,
+        }
+    }
+
+    fn link_name(&self) -> &str {
+        self.properties.link_name.as_deref().unwrap_or("default")
+    }
+
+    /// Stops `old`, starts `new` and links it. Returns whether the lattice reconciled
+    pub fn run(&self, old: &ProviderClaims, new: &ProviderClaims) -> bool {
+        let link_name = self.link_name();
+        let hosts = hosts_running(&Helper::get_host_inventory(), &old.public_key, link_name);
+        for host in &hosts {
+            Helper::stop_provider(&old.public_key, link_name, &old.contract_id, Some(host));
+        }
+        let stopped = format!("Stopped the old {}", self.component.name);
+        self.wait_for(&format!("Waiting for the old {} to stop", self.component.name), &stopped, |inventory, _| {
+            hosts_running(inventory, &old.public_key, link_name).is_empty().then_some(()).ok_or_else(|| vec![old.public_key.clone()])
+        });
+
+        // When nothing was running the lattice picks the host. A failed start is not final,
+        // wadm may have started the new build already, the verification below tells
+        let targets: Vec<Option<&str>> = match hosts.is_empty() {
+            true => vec![None],
+            false => hosts.iter().map(|host| Some(host.as_str())).collect(),
+        };
+        for host in targets {
+            Helper::start_provider(&self.properties.image, link_name, host, self.properties.config.as_ref());
+        }
+
+        let links = self.links(new);
+        for link in &links {
+            Helper::put_link(link);
+        }
+
+        let expected_hosts = if hosts.is_empty() { 1 } else { hosts.len() };
+        let reloaded = format!("Reloaded {} with {} link(s)", self.component.name, links.len());
+        self.wait_for(&format!("Waiting for {} and its links", self.component.name), &reloaded, |inventory, current_links| {
+            let mut pending = Vec::new();
+            if hosts_running(inventory, &new.public_key, link_name).len() < expected_hosts {
+                pending.push(format!("{} on {} host(s)", self.component.name, expected_hosts));
+            }
+            for link in links.iter().filter(|link| !current_links.iter().any(|current| same_link(current, link))) {
+                pending.push(format!("link from {}", link.actor_id));
+            }
+            if pending.is_empty() { Ok(()) } else { Err(pending) }
+        })
+    }
+
+    /// The links of the manifest between an actor and this provider, for the actors whose ID is known:
+    /// linkdefs and `link` traits targeting the provider and `link` traits of the provider targeting an actor
+    fn links(&self, provider: &ProviderClaims) -> Vec<LinkDefinition> {
+        let claims = self.state.get_claims();
+        let inventory = Helper::get_host_inventory();
+
+        let mut links = Vec::new();
+        for source in &self.manifest.spec.components {
+            for t in source.traits.iter().flatten() {
+                let actor = match link_target(t) {
+                    Some(target) if target == self.component.name && source.name != self.component.name => source,
+                    Some(target) if source.name == self.component.name => match self.manifest.spec.components.iter().find(|actor| actor.name == target) {
+                        Some(actor) => actor,
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                let image = match &actor.properties {
+                    Properties::Actor { properties } => &properties.image,
+                    Properties::Capability { .. } => continue,
+                };
+
+                // Local actors are known by their claims, remote ones by their image in the inventory
+                let actor_id = claims.get(&actor.name).map(|claims| claims.get_id()).or_else(|| {
+                    inventory.iter()
+                        .flat_map(|host| host.actors.iter())
+                        .find(|description| description.image_ref.as_deref() == Some(image.as_str()))
+                        .map(|description| description.id.clone())
+                });
+                if let Some(actor_id) = actor_id {
+                    let (link_name, contract_id, values) = self.link_details(t);
+                    links.push(LinkDefinition {
+                        actor_id,
+                        provider_id: provider.public_key.clone(),
+                        link_name,
+                        contract_id,
+                        values,
+                    });
+                }
+            }
+        }
+        links
+    }
+
+    /// Link name, contract and values of a linkdef or `link` trait. A `link` trait has its own name and
+    /// interface, its values are the inline config of the provider's end
+    fn link_details(&self, t: &Trait) -> (String, String, Option<HashMap<String, String>>) {
+        let link = match &t.properties {
+            TraitProperty::Custom(link) => link,
+            TraitProperty::Linkdef(props) => return (self.link_name().to_string(), self.properties.contract.clone(), props.values.clone()),
+            _ => return (self.link_name().to_string(), self.properties.contract.clone(), None),
+        };
+
+        let field = |name: &str| link.get(name).and_then(Value::as_str).filter(|value| !value.is_empty());
+        let contract_id = match (field("namespace"), field("package")) {
+            (Some(namespace), Some(package)) => format!("{}:{}", namespace, package),
+            _ => self.properties.contract.clone(),
+        };
+
+        let end = match link_target(t) == Some(self.component.name.as_str()) {
+            true => "target",
+            false => "source",
+        };
+        let config = link.get(end).and_then(|end| end.get("config")).or_else(|| link.get(format!("{}_config", end)));
+        let values: HashMap<String, String> = config.and_then(Value::as_array).into_iter().flatten()
+            .filter_map(|config| config.get("properties").and_then(Value::as_object))
+            .flatten()
+            .map(|(key, value)| (key.clone(), value.as_str().map(String::from).unwrap_or_else(|| value.to_string())))
+            .collect();
+
+        (field("name").unwrap_or(self.link_name()).to_string(), contract_id, if values.is_empty() { None } else { Some(values) })
+    }
+
+    /// Polls the lattice until `check` passes or `RELOAD_TIMEOUT` is reached. `check` returns what is still pending
+    fn wait_for<F>(&self, message: &str, done: &str, check: F) -> bool
+        where F: Fn(&[LovalHostInventory], &[LinkDefinition]) -> Result<(), Vec<String>>
+    {
+        let task = Progress::spinner(message.to_string());
+        let started = Instant::now();
+        loop {
+            match check(&Helper::get_host_inventory(), &Helper::get_links()) {
+                Ok(()) => {
+                    task.finish(done.to_string());
+                    return true;
+                }
+                Err(pending) if started.elapsed() > RELOAD_TIMEOUT => {
+                    task.fail(format!("Timed out reloading {}, still waiting for {}", self.component.name, pending.join(", ")));
+                    return false;
+                }
+                Err(_) => std::thread::sleep(RELOAD_POLL_INTERVAL),
+            }
+        }
+    }
+}
+
+/// Hosts running the provider instance with this service key and link name
+fn hosts_running(inventory: &[LovalHostInventory], provider_id: &str, link_name: &str) -> Vec<String> {
+    let mut hosts: Vec<String> = inventory.iter()
+        .filter(|host| host.providers.iter().any(|provider| provider.id == provider_id && provider.link_name == link_name))
+        .map(|host| host.host_id.clone())
+        .collect();
+    hosts.sort();
+    hosts.dedup();
+    hosts
+}
+
+fn same_link(a: &LinkDefinition, b: &LinkDefinition) -> bool {
+    a.actor_id == b.actor_id && a.provider_id == b.provider_id && a.link_name == b.link_name
+}
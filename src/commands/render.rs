@@ -0,0 +1,23 @@
+use wadm::model::Manifest;
+use crate::logger::Logger;
+use crate::schema;
+
+/// Prints the manifest exactly as kuwash would put it to wadm
+pub struct RenderCommand {
+    pub manifest: Manifest,
+}
+
+impl RenderCommand {
+    pub fn new(manifest: Manifest) -> RenderCommand {
+        RenderCommand {
+            manifest,
+        }
+    }
+
+    pub fn start(&self) {
+        match schema::to_yaml(&self.manifest) {
+            Ok(yaml) => print!("{}", yaml),
+            Err(error) => Logger::error_and_exit(format!("Could not render the manifest: {}", error)),
+        }
+    }
+}
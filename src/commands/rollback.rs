@@ -0,0 +1,108 @@
+use std::path::Path;
+use colored::*;
+use wadm::model::{Manifest, Properties};
+use crate::arguments::RollbackArgs;
+use crate::commands::app::AppCommand;
+use crate::helper::{AppVersion, Helper};
+use crate::history::{file_digest, AppHistory, VersionRecord};
+use crate::logger::Logger;
+use crate::manifest::suggest;
+use crate::table::human_age;
+
+/// Redeploys a version of an app wadm still stores.
+///
+/// `file://` images are read from disk by the hosts when the version is deployed,
+/// so a version is only rolled back to when its local artifacts are still the ones
+/// that were put. kuwash records their digests on every put to tell.
+pub struct RollbackCommand {
+    pub arguments: RollbackArgs,
+}
+
+impl RollbackCommand {
+    pub fn new(arguments: &RollbackArgs) -> RollbackCommand {
+        RollbackCommand {
+            arguments: arguments.clone(),
+        }
+    }
+
+    pub fn start(&self) {
+        let app_name = &self.arguments.app_name;
+        let versions = Helper::get_app_versions(app_name);
+        if versions.is_empty() {
+            Logger::error_and_exit(format!("App {} has no versions stored in wadm", app_name));
+        }
+
+        let version = self.target_version(&versions);
+        if versions.iter().any(|v| v.version == version && v.deployed) {
+            Logger::info(format!("App {}:{} is already deployed", app_name, version));
+            return;
+        }
+
+        let manifest = Helper::get_app_manifest(app_name, Some(&version))
+            .unwrap_or_else(|| Logger::error_and_exit(format!("wadm returned no manifest for {}:{}", app_name, version)));
+
+        let history = AppHistory::load(app_name);
+        let problems = changed_artifacts(&manifest, history.get(&version));
+        if !problems.is_empty() {
+            for problem in &problems {
+                println!("{}: {}", "error".red().bold(), problem);
+            }
+            Logger::error_and_exit(format!(
+                "Refusing to roll back {} to {}, its local artifacts are not the ones that were put. Rebuild the sources of {} and deploy them under a new version instead",
+                app_name, version, version
+            ));
+        }
+
+        AppCommand::new(manifest, self.arguments.force).rollback(self.arguments.yes);
+    }
+
+    /// The requested version, or the one stored before the deployed version
+    fn target_version(&self, versions: &[AppVersion]) -> String {
+        let app_name = &self.arguments.app_name;
+        if let Some(version) = &self.arguments.version {
+            if !versions.iter().any(|v| &v.version == version) {
+                let hint = suggest(version, versions.iter().map(|v| v.version.as_str()))
+                    .map(|candidate| format!(", did you mean {}?", candidate))
+                    .unwrap_or(".".to_string());
+                Logger::error_and_exit(format!("App {} has no version {} stored in wadm{} Run `kuwash history {}` to list them", app_name, version, hint, app_name));
+            }
+            return version.clone();
+        }
+
+        match versions.iter().position(|v| v.deployed) {
+            Some(0) => Logger::error_and_exit(format!("App {} has no version stored before the deployed {}", app_name, versions[0].version)),
+            Some(index) => versions[index - 1].version.clone(),
+            None => Logger::error_and_exit(format!("App {} is not deployed, name the version to roll back to", app_name)),
+        }
+    }
+}
+
+/// `file://` artifacts of the version that are missing, rebuilt since the put or were never recorded
+fn changed_artifacts(manifest: &Manifest, record: Option<&VersionRecord>) -> Vec<String> {
+    let version = manifest.version();
+    let mut problems = Vec::new();
+
+    for component in &manifest.spec.components {
+        let image = match &component.properties {
+            Properties::Actor { properties } => &properties.image,
+            Properties::Capability { properties } => &properties.image,
+        };
+        let path = match Helper::local_image_path(image) {
+            Some(path) => path,
+            None => continue,
+        };
+
+        let recorded = record.and_then(|record| record.images.iter().find(|i| i.component == component.name && &i.image == image));
+        match (file_digest(Path::new(&path)), recorded.and_then(|i| i.sha256.as_ref())) {
+            (None, _) => problems.push(format!("{}: {} no longer exists", component.name, path)),
+            (Some(_), None) => problems.push(format!("{}: {} was not recorded when {} was put, kuwash cannot tell whether it changed", component.name, path, version)),
+            (Some(current), Some(recorded)) if &current != recorded => {
+                let put = record.map(|record| human_age(record.put_at)).unwrap_or_default();
+                problems.push(format!("{}: {} was rebuilt since {} was put {}", component.name, path, version, put));
+            }
+            _ => {}
+        }
+    }
+
+    problems
+}
@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use colored::*;
+use serde::Serialize;
+use wadm::model::{Component, Manifest, Properties, TraitProperty, DAEMONSCALER_TRAIT, SPREADSCALER_TRAIT};
+use wasmcloud_interface_lattice_control::LabelsMap;
+use crate::arguments::{OutputFormat, StatusArgs};
+use crate::helper::{Helper, LovalHostInventory};
+use crate::logger::Logger;
+use crate::schema;
+use crate::table::Table;
+
+/// How a single manifest component compares to what is running in the lattice
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentStatus {
+    pub name: String,
+    pub kind: String,
+    pub image: String,
+    pub local: bool,
+    /// None when the component has no scaler trait
+    pub expected: Option<usize>,
+    pub running: usize,
+    pub hosts: Vec<String>,
+    /// Claims IDs of the local artifact and of the running instances
+    pub ids: Vec<String>,
+    pub warnings: Vec<String>,
+    /// Why the claims of the local artifact could not be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Answers "is my app actually running as declared?" by comparing every
+/// manifest component with the hosts and inventory of the lattice
+pub struct StatusCommand {
+    pub manifest: Manifest,
+    pub arguments: StatusArgs,
+}
+
+impl StatusCommand {
+    pub fn new(manifest: Manifest, arguments: &StatusArgs) -> StatusCommand {
+        StatusCommand {
+            manifest,
+            arguments: arguments.clone(),
+        }
+    }
+
+    pub fn start(&self) {
+        // Only the report goes to stdout
+        Logger::set_quiet(true);
+
+        let hosts = Helper::get_hosts();
+        let inventory = Helper::get_host_inventory();
+        let statuses: Vec<ComponentStatus> = self.manifest.spec.components.iter()
+            .map(|component| component_status(component, &schema::component_id(&self.manifest, component), &inventory))
+            .collect();
+
+        match self.arguments.output {
+            OutputFormat::Json => {
+                println!("{}", serde_json::to_string_pretty(&statuses).unwrap());
+            }
+            OutputFormat::Table => {
+                println!("App {}:{} on {} host(s)\n", self.manifest.metadata.name, self.manifest.version(), hosts.len());
+                if hosts.is_empty() {
+                    println!("{} No hosts are running in the lattice\n", "warning:".yellow().bold());
+                }
+
+                let mut table = Table::new(&["COMPONENT", "KIND", "IMAGE", "INSTANCES", "HOSTS", "IDS"]);
+                for status in &statuses {
+                    table.add_row(vec![
+                        status.name.clone(),
+                        status.kind.clone(),
+                        if status.local { "local".to_string() } else { "remote".to_string() },
+                        format!("{}/{}", status.running, status.expected.map(|e| e.to_string()).unwrap_or("-".to_string())),
+                        status.hosts.iter().map(|host| short_id(host)).collect::<Vec<_>>().join(","),
+                        status.error.iter().map(|_| "unreadable".to_string())
+                            .chain(status.ids.iter().map(|id| short_id(id)))
+                            .collect::<Vec<_>>().join(","),
+                    ]);
+                }
+                table.print();
+
+                let warnings: Vec<(&String, &String)> = statuses.iter()
+                    .flat_map(|status| status.warnings.iter().map(move |warning| (&status.name, warning)))
+                    .collect();
+                if !warnings.is_empty() {
+                    println!();
+                }
+                for (name, warning) in warnings {
+                    println!("{} {}: {}", "drift:".yellow().bold(), name, warning);
+                }
+                for status in statuses.iter().filter(|status| status.error.is_some()) {
+                    println!("{} {}: {}", "error:".red().bold(), status.name, status.error.as_deref().unwrap_or_default());
+                }
+            }
+        }
+    }
+}
+
+fn component_status(component: &Component, id: &str, inventory: &[LovalHostInventory]) -> ComponentStatus {
+    let (kind, image, link_name) = match &component.properties {
+        Properties::Actor { properties } => ("actor", properties.image.clone(), None),
+        Properties::Capability { properties } => ("capability", properties.image.clone(), Some(properties.link_name.clone().unwrap_or("default".to_string()))),
+    };
+
+    let mut warnings = Vec::new();
+    let mut error = None;
+    let local_path = Helper::local_image_path(&image);
+    let local_id = match &local_path {
+        Some(path) if Path::new(path).exists() => match Helper::try_inspect_image(path, id) {
+            Ok(claims) => Some(claims.get_id()),
+            Err(reason) => {
+                error = Some(reason.trim().to_string());
+                None
+            }
+        },
+        Some(path) => {
+            warnings.push(format!("local artifact {} does not exist, build the component first", path));
+            None
+        }
+        None => None,
+    };
+
+    // Instance count and IDs running per host
+    let mut per_host: BTreeMap<String, (usize, Vec<String>)> = BTreeMap::new();
+    for host in inventory {
+        match &component.properties {
+            Properties::Actor { .. } => {
+                for actor in &host.actors {
+                    if actor.image_ref.as_deref() == Some(image.as_str()) || local_id.as_deref() == Some(actor.id.as_str()) {
+                        let entry = per_host.entry(host.host_id.clone()).or_default();
+                        entry.0 += actor.instances.len();
+                        entry.1.push(actor.id.clone());
+                    }
+                }
+            }
+            Properties::Capability { .. } => {
+                for provider in &host.providers {
+                    let same_image = provider.image_ref.as_deref() == Some(image.as_str()) || local_id.as_deref() == Some(provider.id.as_str());
+                    if same_image && link_name.as_deref() == Some(provider.link_name.as_str()) {
+                        let entry = per_host.entry(host.host_id.clone()).or_default();
+                        entry.0 += 1;
+                        entry.1.push(provider.id.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    let running = per_host.values().map(|(count, _)| count).sum();
+    let expected = expected_instances(component, inventory);
+    let mut ids: Vec<String> = local_id.iter().cloned()
+        .chain(per_host.values().flat_map(|(_, ids)| ids.clone()))
+        .collect();
+    ids.sort();
+    ids.dedup();
+
+    if let Some(local_id) = &local_id {
+        for id in ids.iter().filter(|id| *id != local_id) {
+            warnings.push(format!("running {} which differs from the local artifact {}", id, local_id));
+        }
+    }
+
+    match expected {
+        Some(expected) if running == 0 && expected > 0 => warnings.push("not running".to_string()),
+        Some(expected) if running != expected => warnings.push(format!("expected {} instance(s), {} running", expected, running)),
+        None if running == 0 => warnings.push("no scaler trait, wadm will not start it".to_string()),
+        _ => {}
+    }
+
+    ComponentStatus {
+        name: component.name.clone(),
+        kind: kind.to_string(),
+        image,
+        local: local_path.is_some(),
+        expected,
+        running,
+        hosts: per_host.keys().cloned().collect(),
+        ids,
+        warnings,
+        error,
+    }
+}
+
+/// Instances the scaler traits ask for. A spreadscaler runs `replicas` in total,
+/// a daemonscaler runs `replicas` on every host matching one of its spreads
+fn expected_instances(component: &Component, inventory: &[LovalHostInventory]) -> Option<usize> {
+    let traits = component.traits.as_ref()?;
+    let mut expected = None;
+
+    for t in traits {
+        if let TraitProperty::SpreadScaler(props) = &t.properties {
+            let count = if t.trait_type == DAEMONSCALER_TRAIT {
+                let matching_hosts = inventory.iter()
+                    .filter(|host| props.spread.is_empty() || props.spread.iter().any(|spread| matches_requirements(&host.labels, &spread.requirements)))
+                    .count();
+                props.replicas * matching_hosts
+            } else if t.trait_type == SPREADSCALER_TRAIT {
+                props.replicas
+            } else {
+                continue;
+            };
+            expected = Some(expected.unwrap_or(0) + count);
+        }
+    }
+
+    expected
+}
+
+fn matches_requirements(labels: &LabelsMap, requirements: &BTreeMap<String, String>) -> bool {
+    requirements.iter().all(|(key, value)| labels.get(key) == Some(value))
+}
+
+/// Shortens the 56 character nkeys used as host and component IDs for the table
+fn short_id(id: &str) -> String {
+    if id.chars().count() > 12 {
+        format!("{}…", id.chars().take(12).collect::<String>())
+    } else {
+        id.to_string()
+    }
+}
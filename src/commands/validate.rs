@@ -0,0 +1,40 @@
+use std::process::exit;
+use colored::*;
+use wadm::model::Manifest;
+use crate::arguments::ValidateArgs;
+use crate::logger::Logger;
+use crate::policy::check_policy;
+use crate::validation::{print_problems, Validator};
+
+/// Reports every problem of the manifest at once, including violations of the project's policy
+pub struct ValidateCommand {
+    pub manifest: Manifest,
+    pub arguments: ValidateArgs,
+    /// Profile the manifest was loaded with, policies treat local images differently outside of dev
+    pub profile: Option<String>,
+}
+
+impl ValidateCommand {
+    pub fn new(manifest: Manifest, arguments: &ValidateArgs, profile: Option<String>) -> ValidateCommand {
+        ValidateCommand {
+            manifest,
+            arguments: arguments.clone(),
+            profile,
+        }
+    }
+
+    pub fn start(&self) {
+        Logger::set_quiet(true);
+
+        let mut problems = Validator::new(&self.manifest).validate();
+        problems.extend(check_policy(&self.manifest, &self.arguments.config, self.profile.as_deref()));
+        if problems.is_empty() {
+            println!("{} {:?} is valid", "✔".green(), self.arguments.config);
+            return;
+        }
+
+        print_problems(&problems, &self.arguments.config);
+        println!("{} problem(s) found in {:?}", problems.len(), self.arguments.config);
+        exit(1);
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use crate::logger::Logger;
+
+/// Name of the per project configuration file, looked up next to the manifest
+pub const PROJECT_CONFIG_FILE: &str = "kuwash.yaml";
+
+/// What happens to the application in the lattice when a dev session stops
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExitPolicy {
+    /// Leave the app deployed and running
+    Keep,
+    /// Undeploy the app but keep every stored version
+    Undeploy,
+    /// Undeploy the app and delete the versions this session put
+    #[default]
+    Delete,
+}
+
+/// Per project settings read from `kuwash.yaml` next to the manifest.
+/// Command line flags take precedence over anything set here.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_exit: Option<ExitPolicy>,
+    /// Overlay files of the profiles, relative to the manifest's directory
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, PathBuf>,
+    /// Components built from a local project directory instead of their remote image,
+    /// relative to the manifest's directory. `--local` flags take precedence
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub local: BTreeMap<String, PathBuf>,
+    /// Policy file checked by `validate` and `deploy`, relative to the manifest's directory.
+    /// Defaults to `kuwash.policy.yaml` when it exists
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<PathBuf>,
+    /// Where `kuwash release` pushes the local components
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<RegistryConfig>,
+}
+
+/// An OCI registry, e.g. `localhost:5000/kukuana` for a local `registry:2` container
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryConfig {
+    /// Registry host followed by an optional namespace the artifacts are pushed under
+    pub url: String,
+    /// Push over plain HTTP
+    #[serde(default)]
+    pub insecure: bool,
+}
+
+impl ProjectConfig {
+    pub fn path(manifest_path: &Path) -> PathBuf {
+        manifest_path.parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .join(PROJECT_CONFIG_FILE)
+    }
+
+    /// Loads the project config for a manifest, falling back to the defaults
+    /// when the project has no `kuwash.yaml`
+    pub fn load(manifest_path: &Path) -> ProjectConfig {
+        let path = Self::path(manifest_path);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => return ProjectConfig::default(),
+        };
+
+        match serde_yaml::from_str(&content) {
+            Ok(config) => config,
+            Err(error) => Logger::error_and_exit(format!("Invalid project config {:?}: {}", path, error)),
+        }
+    }
+}
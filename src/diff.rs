@@ -0,0 +1,374 @@
+use std::collections::BTreeMap;
+use colored::*;
+use serde::Serialize;
+use serde_json::Value;
+use wadm::model::{Component, Manifest, Properties, TraitProperty, DAEMONSCALER_TRAIT, LINKDEF_TRAIT, SPREADSCALER_TRAIT};
+use crate::schema::{self, link_target, LINK_TRAIT, SCHEMA_ANNOTATION};
+
+/// Link name of links that set none
+const DEFAULT_LINK_NAME: &str = "default";
+
+/// A single field that differs between two manifests
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "change", rename_all = "lowercase")]
+pub enum Change {
+    Added { field: String, value: String },
+    Removed { field: String, value: String },
+    Modified { field: String, from: String, to: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentDiff {
+    pub name: String,
+    /// `added`, `removed` or `modified`
+    pub status: String,
+    pub changes: Vec<Change>,
+}
+
+/// Structural difference between a stored version of an app and the local manifest.
+///
+/// Components are matched by name and traits by type, links by their target and link name,
+/// so reordering the manifest is not a change. Every component is flattened to
+/// `field: value` pairs such as `properties.image` or `links.httpserver.default.address`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestDiff {
+    pub from_version: Option<String>,
+    pub to_version: String,
+    pub annotations: Vec<Change>,
+    pub components: Vec<ComponentDiff>,
+}
+
+impl ManifestDiff {
+    /// Changes from `deployed` to `local`, everything is added when nothing is deployed
+    pub fn between(deployed: Option<&Manifest>, local: &Manifest) -> ManifestDiff {
+        let empty = BTreeMap::new();
+        let deployed_annotations = deployed.map(|manifest| &manifest.metadata.annotations).unwrap_or(&empty);
+        let annotations = diff_fields(
+            &prefixed("annotations", deployed_annotations),
+            &prefixed("annotations", &local.metadata.annotations),
+        );
+
+        let deployed_manifest = deployed.unwrap_or(local);
+        let deployed_components: Vec<&Component> = deployed.map(|manifest| manifest.spec.components.iter().collect()).unwrap_or_default();
+        let mut components = Vec::new();
+
+        for component in &local.spec.components {
+            match deployed_components.iter().find(|deployed| deployed.name == component.name) {
+                Some(deployed) => {
+                    let changes = diff_fields(&flatten_component(deployed_manifest, deployed), &flatten_component(local, component));
+                    if !changes.is_empty() {
+                        components.push(ComponentDiff { name: component.name.clone(), status: "modified".to_string(), changes });
+                    }
+                }
+                None => components.push(ComponentDiff {
+                    name: component.name.clone(),
+                    status: "added".to_string(),
+                    changes: diff_fields(&BTreeMap::new(), &flatten_component(local, component)),
+                }),
+            }
+        }
+
+        for deployed in deployed_components.iter().filter(|deployed| !local.spec.components.iter().any(|c| c.name == deployed.name)) {
+            components.push(ComponentDiff {
+                name: deployed.name.clone(),
+                status: "removed".to_string(),
+                changes: diff_fields(&flatten_component(deployed_manifest, deployed), &BTreeMap::new()),
+            });
+        }
+
+        ManifestDiff {
+            from_version: deployed.map(|manifest| manifest.version().to_string()),
+            to_version: local.version().to_string(),
+            annotations,
+            components,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.annotations.is_empty() && self.components.is_empty()
+    }
+
+    pub fn print(&self) {
+        if self.is_empty() {
+            println!("No changes to the deployed version {}", self.from_version.as_deref().unwrap_or("-"));
+            return;
+        }
+
+        for change in &self.annotations {
+            println!("{}", format_change(change, ""));
+        }
+        if !self.annotations.is_empty() {
+            println!();
+        }
+
+        for component in &self.components {
+            let header = match component.status.as_str() {
+                "added" => format!("+ {}", component.name).green().bold(),
+                "removed" => format!("- {}", component.name).red().bold(),
+                _ => format!("~ {}", component.name).yellow().bold(),
+            };
+            println!("{}", header);
+            for change in &component.changes {
+                println!("{}", format_change(change, "    "));
+            }
+        }
+
+        if self.from_version.as_deref() == Some(self.to_version.as_str()) {
+            println!(
+                "\n{} the version {} is already stored, wadm will refuse to put the changes until the version annotation is bumped",
+                "note:".yellow().bold(), self.to_version
+            );
+        }
+    }
+}
+
+fn format_change(change: &Change, indent: &str) -> String {
+    match change {
+        Change::Added { field, value } => format!("{}{}", indent, format!("+ {}: {}", field, value).green()),
+        Change::Removed { field, value } => format!("{}{}", indent, format!("- {}: {}", field, value).red()),
+        Change::Modified { field, from, to } => format!("{}{} {}: {} → {}", indent, "~".yellow(), field, from.red(), to.green()),
+    }
+}
+
+fn diff_fields(from: &BTreeMap<String, String>, to: &BTreeMap<String, String>) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (field, value) in to {
+        match from.get(field) {
+            None => changes.push(Change::Added { field: field.clone(), value: value.clone() }),
+            Some(old) if old != value => changes.push(Change::Modified { field: field.clone(), from: old.clone(), to: value.clone() }),
+            _ => {}
+        }
+    }
+    for (field, value) in from.iter().filter(|(field, _)| !to.contains_key(*field)) {
+        changes.push(Change::Removed { field: field.clone(), value: value.clone() });
+    }
+    changes
+}
+
+/// The schema annotation is left out, its fields are compared with their component
+fn prefixed(prefix: &str, values: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    values.iter().filter(|(key, _)| key.as_str() != SCHEMA_ANNOTATION).map(|(key, value)| (format!("{}.{}", prefix, key), value.clone())).collect()
+}
+
+/// The component as `field: value` pairs, with traits keyed by type and links by target and link name
+fn flatten_component(manifest: &Manifest, component: &Component) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+    flatten("", &schema::component_properties(manifest, component), &mut fields);
+
+    for t in component.traits.iter().flatten() {
+        match &t.properties {
+            TraitProperty::Linkdef(props) if t.trait_type == LINKDEF_TRAIT => {
+                let prefix = format!("links.{}.{}", props.target, linkdef_name(manifest, &props.target));
+                let values = props.values.clone().unwrap_or_default();
+                if values.is_empty() {
+                    fields.insert(prefix.clone(), "(no values)".to_string());
+                }
+                for (key, value) in values {
+                    fields.insert(format!("{}.{}", prefix, key), value);
+                }
+            }
+            TraitProperty::SpreadScaler(props) if t.trait_type == SPREADSCALER_TRAIT || t.trait_type == DAEMONSCALER_TRAIT => {
+                fields.insert(format!("{}.replicas", t.trait_type), props.replicas.to_string());
+                for spread in &props.spread {
+                    let prefix = format!("{}.spread.{}", t.trait_type, spread.name);
+                    fields.insert(format!("{}.weight", prefix), spread.weight.map(|weight| weight.to_string()).unwrap_or("-".to_string()));
+                    for (key, value) in &spread.requirements {
+                        fields.insert(format!("{}.requirements.{}", prefix, key), value.clone());
+                    }
+                }
+            }
+            TraitProperty::Custom(props) if t.trait_type == LINK_TRAIT => {
+                let mut props = props.clone();
+                let target = link_target(t).unwrap_or_default();
+                let prefix = format!("links.{}.{}", target, props.get("name").and_then(Value::as_str).unwrap_or(DEFAULT_LINK_NAME));
+                if let Some(props) = props.as_object_mut() {
+                    props.remove("target");
+                }
+                flatten(&prefix, &props, &mut fields);
+            }
+            properties => {
+                flatten(&format!("traits.{}", t.trait_type), &serde_json::to_value(properties).unwrap_or_default(), &mut fields);
+            }
+        }
+    }
+
+    fields
+}
+
+/// A linkdef is made under the link name of the capability it targets
+fn linkdef_name<'a>(manifest: &'a Manifest, target: &str) -> &'a str {
+    manifest.spec.components.iter()
+        .find(|component| component.name == target)
+        .and_then(|component| match &component.properties {
+            Properties::Capability { properties } => properties.link_name.as_deref(),
+            Properties::Actor { .. } => None,
+        })
+        .unwrap_or(DEFAULT_LINK_NAME)
+}
+
+fn flatten(prefix: &str, value: &Value, fields: &mut BTreeMap<String, String>) {
+    let join = |key: &str| if prefix.is_empty() { key.to_string() } else { format!("{}.{}", prefix, key) };
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                flatten(&join(key), value, fields);
+            }
+        }
+        Value::Array(array) => {
+            for (index, value) in array.iter().enumerate() {
+                flatten(&format!("{}[{}]", prefix, index), value, fields);
+            }
+        }
+        Value::String(string) => {
+            fields.insert(prefix.to_string(), string.clone());
+        }
+        Value::Null => {}
+        other => {
+            fields.insert(prefix.to_string(), other.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::manifest::ManifestSource;
+    use super::*;
+
+    const ACTORS: &str = "
+apiVersion: core.oam.dev/v1beta1
+kind: Application
+metadata:
+  name: echo
+  annotations:
+    version: v0.0.1
+spec:
+  components:
+    - name: echo
+      type: actor
+      properties:
+        image: wasmcloud.azurecr.io/echo:0.3.8
+      traits:
+        - type: spreadscaler
+          properties:
+            replicas: 1
+        - type: linkdef
+          properties:
+            target: httpserver
+            values:
+              address: 0.0.0.0:8080
+    - name: httpserver
+      type: capability
+      properties:
+        image: wasmcloud.azurecr.io/httpserver:0.17.0
+        contract: wasmcloud:httpserver
+";
+
+    const COMPONENTS: &str = "
+apiVersion: core.oam.dev/v1beta1
+kind: Application
+metadata:
+  name: hello
+  annotations:
+    version: v0.0.1
+spec:
+  components:
+    - name: http-component
+      type: component
+      properties:
+        image: ghcr.io/wasmcloud/components/http-hello-world-rust:0.1.0
+    - name: httpserver
+      type: capability
+      properties:
+        image: ghcr.io/wasmcloud/http-server:0.20.0
+      traits:
+        - type: link
+          properties:
+            target: http-component
+            namespace: wasi
+            package: http
+            interfaces: [incoming-handler]
+            source_config:
+              - name: public
+                properties:
+                  address: 0.0.0.0:8080
+        - type: link
+          properties:
+            target: http-component
+            name: admin
+            namespace: wasi
+            package: http
+            interfaces: [incoming-handler]
+            source_config:
+              - name: admin
+                properties:
+                  address: 127.0.0.1:9090
+";
+
+    fn manifest(text: &str) -> Manifest {
+        ManifestSource::new(Path::new("wadm.yaml"), text.to_string()).parse().unwrap()
+    }
+
+    fn changes<'a>(diff: &'a ManifestDiff, component: &str) -> &'a [Change] {
+        &diff.components.iter().find(|diff| diff.name == component).unwrap().changes
+    }
+
+    #[test]
+    fn adds_everything_when_nothing_is_deployed() {
+        let diff = ManifestDiff::between(None, &manifest(ACTORS));
+        assert_eq!(diff.from_version, None);
+        assert!(diff.components.iter().all(|component| component.status == "added"));
+        assert!(changes(&diff, "echo").iter().any(|change| matches!(change,
+            Change::Added { field, value } if field == "links.httpserver.default.address" && value == "0.0.0.0:8080")));
+    }
+
+    #[test]
+    fn ignores_the_order_of_components() {
+        let deployed = manifest(ACTORS);
+        let mut local = deployed.clone();
+        local.spec.components.reverse();
+        assert!(ManifestDiff::between(Some(&deployed), &local).is_empty());
+    }
+
+    #[test]
+    fn reports_added_removed_and_modified_components() {
+        let deployed = manifest(ACTORS);
+        let local = manifest(&ACTORS.replace("0.0.0.0:8080", "0.0.0.0:9090").replace("- name: httpserver", "- name: keyvalue"));
+        let diff = ManifestDiff::between(Some(&deployed), &local);
+
+        let statuses: Vec<_> = diff.components.iter().map(|component| (component.name.as_str(), component.status.as_str())).collect();
+        assert_eq!(statuses, vec![("echo", "modified"), ("keyvalue", "added"), ("httpserver", "removed")]);
+        assert!(matches!(&changes(&diff, "echo")[0],
+            Change::Modified { field, from, to } if field == "links.httpserver.default.address" && from == "0.0.0.0:8080" && to == "0.0.0.0:9090"));
+    }
+
+    #[test]
+    fn keys_linkdefs_by_the_link_name_of_their_target() {
+        let deployed = manifest(ACTORS);
+        let local = manifest(&ACTORS.replace("contract: wasmcloud:httpserver", "contract: wasmcloud:httpserver\n        link_name: admin"));
+        let fields: Vec<_> = changes(&ManifestDiff::between(Some(&deployed), &local), "echo").iter()
+            .map(|change| match change {
+                Change::Added { field, .. } => format!("+{}", field),
+                Change::Removed { field, .. } => format!("-{}", field),
+                Change::Modified { field, .. } => format!("~{}", field),
+            })
+            .collect();
+        assert_eq!(fields, vec!["+links.httpserver.admin.address", "-links.httpserver.default.address"]);
+    }
+
+    #[test]
+    fn keeps_links_to_the_same_target_apart_by_name() {
+        let deployed = manifest(COMPONENTS);
+        let local = manifest(&COMPONENTS.replace("127.0.0.1:9090", "127.0.0.1:9191"));
+        let changes = changes(&ManifestDiff::between(Some(&deployed), &local), "httpserver").to_vec();
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(&changes[0],
+            Change::Modified { field, from, to } if field == "links.http-component.admin.source_config[0].properties.address" && from == "127.0.0.1:9090" && to == "127.0.0.1:9191"));
+    }
+
+    #[test]
+    fn leaves_the_schema_annotation_out() {
+        let diff = ManifestDiff::between(None, &manifest(COMPONENTS));
+        assert!(diff.annotations.iter().all(|change| !matches!(change, Change::Added { field, .. } if field.contains(SCHEMA_ANNOTATION))));
+    }
+}
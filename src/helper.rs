@@ -1,11 +1,30 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 use std::process::exit;
-use wadm::model::{Manifest};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use wadm::model::{CapabilityConfig, CapabilityProperties, Manifest, Properties};
 use wasmcloud_interface_lattice_control::{ActorDescriptions, Hosts, LabelsMap, ProviderDescriptions};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use crate::claims::ComponentClaims;
+use crate::history::AppHistory;
 use crate::logger::Logger;
+use crate::manifest::ManifestSource;
+use crate::provider_archive::ProviderArchive;
+use crate::schema;
+use crate::session::state_directory;
+use crate::shutdown::{Shutdown, TrackedCommand};
+
+/// Variables wash reads the lattice from, see `Helper::target_lattice`
+const LATTICE_VARIABLES: [&str; 2] = ["WASMCLOUD_LATTICE", "WASMCLOUD_LATTICE_PREFIX"];
+/// Their values before kuwash first targeted a lattice
+static ORIGINAL_LATTICE: OnceLock<[Option<OsString>; 2]> = OnceLock::new();
+/// Numbers the provider config files of this process, providers may be started in parallel
+static PROVIDER_CONFIGS: AtomicUsize = AtomicUsize::new(0);
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct GetHostInventoriesCommandOutput {
@@ -39,163 +58,130 @@ pub struct LovalHostInventory {
     pub providers: ProviderDescriptions,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
-pub struct StoredActorClaims {
-    pub call_alias: String,
-    #[serde(alias = "caps", deserialize_with = "deserialize_messy_vec")]
-    pub capabilities: Vec<String>,
-    #[serde(alias = "iss")]
-    pub issuer: Option<String>,
+pub struct Helper {}
+
+/// An app stored in wadm, as listed by `wash app list`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AppSummary {
     pub name: String,
-    #[serde(alias = "rev")]
-    pub revision: u16,
-    #[serde(alias = "sub")]
-    pub subject: Option<String>,
-    #[serde(deserialize_with = "deserialize_messy_vec")]
-    pub tags: Vec<String>,
+    /// Latest version put
     pub version: String,
-    pub module: String
+    #[serde(default, alias = "deployedVersion")]
+    pub deployed_version: Option<String>,
 }
 
-fn deserialize_messy_vec<'de, D: serde::Deserializer<'de>>(
-    deserializer: D,
-) -> Result<Vec<String>, D::Error> {
-    MessyVec::deserialize(deserializer).map(|messy_vec| messy_vec.0)
-}
-// Helper struct to deserialize either a comma-delimited string or an actual array of strings
-struct MessyVec(pub Vec<String>);
-
-struct MessyVecVisitor;
-
-// Since this is "temporary" code to preserve backwards compatibility with already-serialized claims,
-// we use fully-qualified names instead of importing
-impl<'de> serde::de::Visitor<'de> for MessyVecVisitor {
-    type Value = MessyVec;
-
-    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        formatter.write_str("string or array of strings")
-    }
-
-    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
-        where
-            A: serde::de::SeqAccess<'de>,
-    {
-        let mut values = Vec::new();
-
-        while let Some(value) = seq.next_element()? {
-            values.push(value);
-        }
-
-        Ok(MessyVec(values))
-    }
-
-    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
-        where
-            E: serde::de::Error,
-    {
-        Ok(MessyVec(value.split(',').map(String::from).collect()))
-    }
-}
-
-impl<'de> Deserialize<'de> for MessyVec {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-        where
-            D: serde::de::Deserializer<'de>,
-    {
-        deserializer.deserialize_any(MessyVecVisitor)
-    }
+/// A link between an actor and a provider, as listed by `wash link query`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LinkDefinition {
+    pub actor_id: String,
+    pub provider_id: String,
+    pub link_name: String,
+    pub contract_id: String,
+    #[serde(default)]
+    pub values: Option<HashMap<String, String>>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
-pub struct StoredProviderClaims {
-    pub capability_contract_id: String,
-    #[serde(alias = "iss")]
-    pub issuer: String,
-    pub name: String,
-    #[serde(alias = "rev")]
-    pub revision: String,
-    // #[serde(alias = "sub")]
-    // pub subject: String,
+/// A version of an app stored in wadm, as listed by `wash app history`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AppVersion {
     pub version: String,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub config_schema: Option<String>,
-    pub service: String,
-    pub targets: Vec<String>,
-    pub vendor: String,
-    pub success: bool,
+    #[serde(default)]
+    pub deployed: bool,
 }
-pub struct Helper {}
 
+impl Helper {
+    pub fn get_manifest_from_wadm_config(path: &Path) -> Result<Manifest, Box<dyn std::error::Error>> {
+        let manifest = ManifestSource::read(path)?.parse()?;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-#[serde(untagged)]
-pub enum ComponentClaims {
-    Actor(StoredActorClaims),
-    Provider(StoredProviderClaims)
-}
+        Ok(manifest)
+    }
 
-impl ComponentClaims {
-    pub fn get_actor_claims(&self) -> StoredActorClaims {
-        match self {
-            ComponentClaims::Actor(claims) => claims.clone(),
-            _ => panic!("This is not an actor")
-        }
+    /// The path of a local `file://` image, None for remote images
+    pub fn local_image_path(image: &str) -> Option<String> {
+        image.strip_prefix("file://").map(String::from)
     }
 
-    pub fn get_provider_claims(&self) -> StoredProviderClaims {
-        match self {
-            ComponentClaims::Provider(claims) => claims.clone(),
-            _ => panic!("This is not a provider")
-        }
+    /// The project directory of a local image, i.e. the path without `build/<name>.wasm`
+    /// or `build/<name>.par.gz`
+    pub fn local_repo_path(image_path: &str) -> String {
+        let actor_image_regex = Regex::new(r"/build/([^/]+)\.wasm").unwrap();
+        let provider_image_regex = Regex::new(r"/build/([^/]+)\.par.gz").unwrap();
+        let repo_path: String = actor_image_regex.replace(image_path, "").into();
+        provider_image_regex.replace(&repo_path, "").into()
     }
-}
 
-impl Helper {
-    pub fn get_manifest_from_wadm_config(path: &PathBuf) -> Result<Manifest, Box<dyn std::error::Error>> {
-        let yaml_str = fs::read_to_string(path)?;
-        let manifest: Manifest = serde_yaml::from_str(&yaml_str)?;
+    /// The artifact a project directory builds to: the newest one already in `build/`,
+    /// otherwise the name wash or kuwash give it, from `wasmcloud.toml` or `Cargo.toml`
+    pub fn local_artifact_path(project: &Path, provider: bool) -> Option<PathBuf> {
+        let suffix = if provider { ".par.gz" } else { "_s.wasm" };
+        let built = fs::read_dir(project.join("build")).ok()
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(suffix))
+            .max_by_key(|entry| entry.metadata().and_then(|metadata| metadata.modified()).ok())
+            .map(|entry| entry.path());
+        if built.is_some() {
+            return built;
+        }
 
-        Ok(manifest)
+        let name = ["wasmcloud.toml", "Cargo.toml"].iter()
+            .filter_map(|file| fs::read_to_string(project.join(file)).ok())
+            .filter_map(|content| content.parse::<toml::Table>().ok())
+            .find_map(|table| {
+                table.get("name")
+                    .or_else(|| table.get("package").and_then(|package| package.get("name")))
+                    .and_then(|name| name.as_str())
+                    .map(String::from)
+            })?;
+
+        let file = if provider { format!("{}.par.gz", name) } else { format!("{}{}", name.replace('-', "_"), suffix) };
+        Some(project.join("build").join(file))
     }
 
     pub fn does_wash_cli_exist() -> bool {
-        let output = process::Command::new("which")
-            .arg("wash")
-            .output().expect("which binary not found. Please use in unix system");
+        Self::find_executable("wash").is_some()
+    }
 
-        return output.status.success();
+    /// Looks `name` up in the directories of `PATH` like `which` does, without needing `which`
+    pub fn find_executable(name: &str) -> Option<PathBuf> {
+        let paths = std::env::var_os("PATH")?;
+        std::env::split_paths(&paths)
+            .map(|directory| directory.join(name))
+            .find(|candidate| is_executable(candidate))
     }
 
     pub fn get_host_inventory() -> Vec<LovalHostInventory> {
+        Self::try_get_host_inventory().unwrap_or_else(|error| {
+            Logger::error(error);
+            Shutdown::wait_if_shutting_down();
+            exit(1);
+        })
+    }
+
+    /// The inventory of every host, returning the error instead of exiting
+    pub fn try_get_host_inventory() -> Result<Vec<LovalHostInventory>, String> {
         let output = process::Command::new("wash")
             .args(["get", "inventory", "-o", "json"])
-            .output().expect("Failed to execute wash binary");
-
-        if output.status.success() {
-            let output_str = String::from_utf8_lossy(&output.stdout);
-
-            let result: GetHostInventoriesCommandOutput = serde_json::from_str(&output_str).expect("failed");
-            if result.success {
-                return result.inventories.unwrap()
-            } else {
-                Logger::error(result.error.unwrap());
-                exit(1);
-            }
-
-        } else {
-            let error_str = String::from_utf8_lossy(&output.stderr);
-            Logger::error(error_str.to_string());
-            exit(1);
+            .tracked_output().expect("Failed to execute wash binary");
 
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).to_string());
         }
 
+        let result: GetHostInventoriesCommandOutput = serde_json::from_slice(&output.stdout)
+            .map_err(|error| format!("Unexpected output of wash get inventory: {}", error))?;
+        match result.success {
+            true => Ok(result.inventories.unwrap_or_default()),
+            false => Err(result.error.unwrap_or_default()),
+        }
     }
 
     pub fn get_hosts() -> Hosts {
         Logger::info("Getting current running Hosts...".to_string());
         let output = process::Command::new("wash")
             .args(["get", "hosts", "-o", "json"])
-            .output().expect("Failed to execute wash binary");
+            .tracked_output().expect("Failed to execute wash binary");
 
         if output.status.success() {
             let output_str = String::from_utf8_lossy(&output.stdout);
@@ -204,41 +190,144 @@ impl Helper {
                 return result.hosts.unwrap()
             } else {
                 Logger::error(result.error.unwrap());
+                Shutdown::wait_if_shutting_down();
                 exit(1);
             }
 
         } else {
             let error_str = String::from_utf8_lossy(&output.stderr);
             Logger::error(error_str.to_string());
+            Shutdown::wait_if_shutting_down();
             exit(1);
 
         }
     }
 
-    pub fn inspect_images(path: String) -> ComponentClaims {
-        Logger::info(format!("Getting image information for image file: {}", &path));
-        let output = process::Command::new("wash")
-            .args(["inspect", &path, "-o", "json"])
-            .output().expect("Failed to execute wash binary");
+    /// Points every later wash call of this process at `lattice`, whatever lattice the current
+    /// wash context uses, or back at the lattice of the context with None. Both variables are set,
+    /// wash renamed the lattice prefix to lattice
+    pub fn target_lattice(lattice: Option<&str>) {
+        let original = ORIGINAL_LATTICE.get_or_init(|| LATTICE_VARIABLES.map(std::env::var_os));
+        for (variable, value) in LATTICE_VARIABLES.iter().zip(original) {
+            match lattice.map(OsString::from).or_else(|| value.clone()) {
+                Some(value) => std::env::set_var(variable, value),
+                None => std::env::remove_var(variable),
+            }
+        }
+    }
 
-        if output.status.success() {
-            let output_str = String::from_utf8_lossy(&output.stdout);
-            println!("{}", output_str);
-            let result: ComponentClaims = serde_json::from_str(&output_str).expect("failed");
-            result
+    /// The lattice prefix reported by the running hosts
+    pub fn get_lattice(hosts: &Hosts) -> String {
+        hosts.iter()
+            .find_map(|host| host.lattice_prefix.clone())
+            .unwrap_or_else(|| "default".to_string())
+    }
 
-        } else {
-            let error_str = String::from_utf8_lossy(&output.stderr);
-            Logger::error(error_str.to_string());
-            exit(1);
+    pub fn inspect_images(path: String, id: &str) -> ComponentClaims {
+        match Self::try_inspect_image(&path, id) {
+            Ok(claims) => claims,
+            Err(error) => Logger::error_and_exit(error),
+        }
+    }
+
+    /// Reads the claims embedded in a local artifact, returning the error instead of exiting.
+    /// `id` is what a component without claims is known by, see `schema::component_id`
+    pub fn try_inspect_image(path: &str, id: &str) -> Result<ComponentClaims, String> {
+        ComponentClaims::read(Path::new(path), id)
+    }
+
+    /// Puts the manifest as kuwash parsed it rather than the file on disk,
+    /// so that expanded variables reach wadm
+    pub fn put_manifest(manifest: &Manifest) {
+        let directory = state_directory().join("rendered");
+        let path = directory.join(format!("{}-{}.yaml", manifest.metadata.name, process::id()));
+        let written = schema::to_yaml(manifest)
+            .and_then(|yaml| fs::create_dir_all(&directory).and_then(|_| fs::write(&path, yaml)).map_err(|error| error.to_string()));
+
+        if let Err(error) = written {
+            Logger::error_and_exit(format!("Could not write the rendered manifest to {:?}: {}", path, error));
+        }
+
+        Self::put_app(&path.to_string_lossy());
+        let _ = fs::remove_file(&path);
+        AppHistory::record(manifest);
+    }
+
+    pub fn get_apps() -> Vec<AppSummary> {
+        Self::try_get_apps().unwrap_or_else(|error| {
+            Shutdown::wait_if_shutting_down();
+            Logger::error_and_exit(error)
+        })
+    }
+
+    /// Every app wadm stores, returning the error instead of exiting
+    pub fn try_get_apps() -> Result<Vec<AppSummary>, String> {
+        let output = Self::try_wash_app_json(&["list"])?;
+        let apps = find_key(&output, &["models", "applications", "apps"]).cloned().unwrap_or_default();
+        serde_json::from_value(apps).map_err(|error| format!("Unexpected output of wash app list: {}", error))
+    }
+
+    pub fn get_app(app_name: &str) -> Option<AppSummary> {
+        Self::get_apps().into_iter().find(|app| app.name == app_name)
+    }
+
+    /// The app, None when wadm does not know it, returning the error instead of exiting
+    pub fn try_get_app(app_name: &str) -> Result<Option<AppSummary>, String> {
+        Ok(Self::try_get_apps()?.into_iter().find(|app| app.name == app_name))
+    }
+
+    /// Every version of an app wadm stores, oldest first
+    pub fn get_app_versions(app_name: &str) -> Vec<AppVersion> {
+        Self::try_get_app_versions(app_name).unwrap_or_else(|error| {
+            Shutdown::wait_if_shutting_down();
+            Logger::error_and_exit(error)
+        })
+    }
+
+    /// Every version of an app wadm stores, returning the error instead of exiting
+    pub fn try_get_app_versions(app_name: &str) -> Result<Vec<AppVersion>, String> {
+        let output = Self::try_wash_app_json(&["history", app_name])?;
+        let versions = find_key(&output, &["versions", "revisions", "history"]).cloned().unwrap_or_default();
+        serde_json::from_value(versions).map_err(|error| format!("Unexpected output of wash app history: {}", error))
+    }
+
+    /// A stored version of an app, the latest one put when no version is given.
+    /// None when wadm does not know the app or version
+    pub fn get_app_manifest(app_name: &str, version: Option<&str>) -> Option<Manifest> {
+        let mut args = vec!["get", app_name];
+        args.extend(version);
+        let output = Self::wash_app_json(&args);
+        let manifest = find_key(&output, &["manifest"]).filter(|manifest| !manifest.is_null())?;
+        match schema::from_value(manifest.clone()) {
+            Ok(manifest) => Some(manifest),
+            Err(error) => Logger::error_and_exit(format!("Unexpected manifest from wash app get: {}", error)),
+        }
+    }
+
+    fn wash_app_json(args: &[&str]) -> serde_json::Value {
+        Self::try_wash_app_json(args).unwrap_or_else(|error| {
+            Shutdown::wait_if_shutting_down();
+            Logger::error_and_exit(error)
+        })
+    }
+
+    fn try_wash_app_json(args: &[&str]) -> Result<serde_json::Value, String> {
+        let output = process::Command::new("wash")
+            .arg("app").args(args).args(["-o", "json"])
+            .tracked_output().expect("Failed to execute wash binary");
+
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).to_string());
         }
+
+        serde_json::from_slice(&output.stdout).map_err(|error| format!("Unexpected output of wash app {}: {}", args[0], error))
     }
 
     pub fn put_app(manifest_path: &str) {
         Logger::info(format!("Putting App Spec for: {}", &manifest_path));
         let output = process::Command::new("wash")
             .args(["app", "put", &manifest_path, "-o", "json"])
-            .output().expect("Failed to execute wash binary");
+            .tracked_output().expect("Failed to execute wash binary");
 
         if output.status.success() {
             Logger::info("App model successfully added".into());
@@ -246,17 +335,21 @@ impl Helper {
         } else {
             let error_str = String::from_utf8_lossy(&output.stderr);
             Logger::error(error_str.to_string());
+            Shutdown::wait_if_shutting_down();
             exit(1);
         }
     }
 
     pub fn deploy_app(manifest: &Manifest) {
-        let app_version = manifest.metadata.annotations.get("version").unwrap();
+        let app_version = manifest.version();
         let app_name = &manifest.metadata.name;
+        if app_version.is_empty() {
+            Logger::error_and_exit(format!("App {} has no version annotation, add one so wadm can deploy it", app_name));
+        }
         Logger::info(format!("Deploying App {}:{}", &app_name, app_version));
         let output = process::Command::new("wash")
             .args(["app", "deploy",  &app_name, "-o", "json"])
-            .output().expect("Failed to execute wash binary");
+            .tracked_output().expect("Failed to execute wash binary");
 
         if output.status.success() {
             Logger::info(format!("Application {}:{} deployed successfully \n", &app_name, app_version));
@@ -264,41 +357,83 @@ impl Helper {
         } else {
             let error_str = String::from_utf8_lossy(&output.stderr);
             Logger::error(error_str.to_string());
+            Shutdown::wait_if_shutting_down();
             exit(1);
         }
     }
 
-    pub fn undeploy_app(manifest: &Manifest) {
-        let app_name = &manifest.metadata.name;
+    /// Deploys a version of an app that is already stored in wadm
+    pub fn deploy_app_version(app_name: &str, version: &str) {
+        Logger::info(format!("Deploying App {}:{}", app_name, version));
+        let output = process::Command::new("wash")
+            .args(["app", "deploy", app_name, version, "-o", "json"])
+            .tracked_output().expect("Failed to execute wash binary");
+
+        if output.status.success() {
+            Logger::info(format!("Application {}:{} deployed successfully \n", app_name, version));
+            Logger::info(format!("{}", String::from_utf8_lossy(&output.stdout)));
+        } else {
+            let error_str = String::from_utf8_lossy(&output.stderr);
+            Logger::error(error_str.to_string());
+            Shutdown::wait_if_shutting_down();
+            exit(1);
+        }
+    }
+
+    pub fn undeploy_app(manifest: &Manifest) -> bool {
+        Self::undeploy_app_by_name(&manifest.metadata.name)
+    }
+
+    pub fn undeploy_app_by_name(app_name: &str) -> bool {
         Logger::info(format!("Undeploying App {}", &app_name));
         let output = process::Command::new("wash")
-            .args(["app", "undeploy",  &app_name, "-o", "json"])
-            .output().expect("Failed to execute wash binary");
+            .args(["app", "undeploy",  app_name, "-o", "json"])
+            .tracked_output().expect("Failed to execute wash binary");
 
         if output.status.success() {
             Logger::info(format!("Application {} undeployed successfully \n", &app_name));
             Logger::info(format!("{}", String::from_utf8_lossy(&output.stdout)));
+            true
         } else {
             let error_str = String::from_utf8_lossy(&output.stderr);
             Logger::error(error_str.to_string());
-            exit(1);
+            false
         }
     }
 
-    pub fn delete_app(manifest: &Manifest) {
+    pub fn delete_app(manifest: &Manifest) -> bool {
         let app_name = &manifest.metadata.name;
         Logger::info(format!("Deleting App {}", &app_name));
         let output = process::Command::new("wash")
             .args(["app", "delete", app_name,  "--delete-all", "-o", "json"])
-            .output().expect("Failed to execute wash binary");
+            .tracked_output().expect("Failed to execute wash binary");
 
         if output.status.success() {
             Logger::info(format!("Application {} deleted successfully \n", &app_name));
             Logger::info(format!("{}", String::from_utf8_lossy(&output.stdout)));
+            true
         } else {
             let error_str = String::from_utf8_lossy(&output.stderr);
             Logger::error(error_str.to_string());
-            exit(1);
+            false
+        }
+    }
+
+    /// Deletes a single version of an application, leaving its other versions in place
+    pub fn delete_app_version(app_name: &str, version: &str) -> bool {
+        Logger::info(format!("Deleting App {}:{}", app_name, version));
+        let output = process::Command::new("wash")
+            .args(["app", "delete", app_name, version, "-o", "json"])
+            .tracked_output().expect("Failed to execute wash binary");
+
+        if output.status.success() {
+            Logger::info(format!("Application {}:{} deleted successfully \n", app_name, version));
+            Logger::info(format!("{}", String::from_utf8_lossy(&output.stdout)));
+            true
+        } else {
+            let error_str = String::from_utf8_lossy(&output.stderr);
+            Logger::error(error_str.to_string());
+            false
         }
     }
 
@@ -306,7 +441,7 @@ impl Helper {
         Logger::info(format!("Building actor at {path:?}"));
         let output = process::Command::new("wash").current_dir(path)
             .args(["build", "-o", "json"])
-            .output().expect("Failed to execute wash binary");
+            .tracked_output().expect("Failed to execute wash binary");
 
         if output.status.success() {
             Logger::info("Actor built successfully \n".into());
@@ -320,30 +455,110 @@ impl Helper {
         }
     }
 
-    pub fn build_provider(path: &str) -> bool {
-        Logger::info(format!("Building provider at {path:?}"));
-        let output = process::Command::new("make").current_dir(path)
-            .output().expect("Failed to execute make binary");
+    /// Builds a provider to the archive its image points to. Cargo projects without a Makefile
+    /// are built and packaged natively, see `ProviderArchive`, other projects with their Makefile
+    pub fn build_provider(properties: &CapabilityProperties) -> bool {
+        Self::package_provider(properties, false)
+    }
 
-        return if output.status.success() {
+    /// Builds a component for a release: `wash build` always builds actors in release mode,
+    /// providers are built with `cargo build --release`, or the `release` target of their Makefile
+    pub fn build_release(properties: &Properties) -> bool {
+        match properties {
+            Properties::Actor { properties } => match Self::local_image_path(&properties.image) {
+                Some(artifact) => Self::build_actor(&Self::local_repo_path(&artifact)),
+                None => false,
+            },
+            Properties::Capability { properties } => Self::package_provider(properties, true),
+        }
+    }
+
+    fn package_provider(properties: &CapabilityProperties, release: bool) -> bool {
+        let archive = match Self::local_image_path(&properties.image) {
+            Some(archive) => archive,
+            None => {
+                Logger::error(format!("{} is not a local provider, it cannot be built", properties.image));
+                return false;
+            }
+        };
+        let path = Self::local_repo_path(&archive);
+        if !ProviderArchive::supports(Path::new(&path)) {
+            if Path::new(&path).join("Cargo.toml").exists() {
+                Logger::info(format!("The cargo project at {path:?} has a Makefile, building it with make rather than packaging it natively"));
+            }
+            return Self::build_provider_with_make(&path, release);
+        }
+
+        let mode = if release { " in release mode" } else { "" };
+        Logger::info(format!("Building provider at {path:?} natively with cargo{mode}"));
+        match ProviderArchive::new(Path::new(&path), Path::new(&archive), &properties.contract).build(release) {
+            Ok(()) => {
+                Logger::info("Provider built successfully \n".into());
+                true
+            }
+            Err(error) => {
+                Logger::error(error);
+                false
+                // Do not exit on build failure to allow for hot reload when build is fixed
+            }
+        }
+    }
+
+    /// Providers that are not built natively are built by their Makefile,
+    /// with its `release` target for releases when it has one
+    fn build_provider_with_make(path: &str, release: bool) -> bool {
+        let has_release_target = fs::read_to_string(Path::new(path).join("Makefile"))
+            .map(|makefile| makefile.lines().any(|line| line.starts_with("release:")))
+            .unwrap_or(false);
+        if release && !has_release_target {
+            Logger::info(format!("The Makefile at {path:?} has no release target, building the default target"));
+        }
+
+        Logger::info(format!("Building provider at {path:?} with make"));
+        let mut command = process::Command::new("make");
+        command.current_dir(path);
+        if release && has_release_target {
+            command.arg("release");
+        }
+        let output = command.tracked_output().expect("Failed to execute make binary");
+
+        if output.status.success() {
             Logger::info("Provider built successfully \n".into());
             Logger::info(format!("{}", String::from_utf8_lossy(&output.stdout)));
             true
         } else {
-            let error_str = String::from_utf8_lossy(&output.stderr);
-            Logger::error(error_str.to_string());
+            Logger::error(String::from_utf8_lossy(&output.stderr).to_string());
             false
-            // Do not exit on build failure to allow for hot reload when build is fixed
-            // Logger::error("Build is failing so we have to exit. Sorry".into());
-            // exit(1);
         }
     }
 
+    /// Pushes an actor or provider archive to an OCI reference, returning the digest the registry stored it under
+    pub fn push_artifact(reference: &str, artifact: &str, insecure: bool) -> Result<String, String> {
+        Logger::info(format!("Pushing {} to {}", artifact, reference));
+        let mut command = process::Command::new("wash");
+        command.args(["push", reference, artifact, "-o", "json"]);
+        if insecure {
+            command.arg("--insecure");
+        }
+        let output = command.tracked_output().map_err(|error| format!("Failed to execute wash binary: {}", error))?;
+
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).to_string());
+        }
+
+        let result: serde_json::Value = serde_json::from_slice(&output.stdout)
+            .map_err(|error| format!("Unexpected output of wash push: {}", error))?;
+        find_key(&result, &["digest"])
+            .and_then(|digest| digest.as_str())
+            .map(String::from)
+            .ok_or_else(|| format!("wash push reported no digest for {}", reference))
+    }
+
     pub fn build_project_with_cargo(path: &str) -> bool {
         Logger::info(format!("Building project with cargo {path:?}"));
         let output = process::Command::new("cargo").current_dir(path)
             .args(["build", "--release"])
-            .output().expect("Failed to execute cargo binary");
+            .tracked_output().expect("Failed to execute cargo binary");
 
         return if output.status.success() {
             Logger::info("Project built successfully \n".into());
@@ -359,11 +574,28 @@ impl Helper {
         }
     }
 
+    /// Removes the build output of a provider: `cargo clean` and its `build` directory
+    /// for projects built natively, `make clean` for the others
     pub fn clean_provider(path: &str) -> bool {
         Logger::info(format!("Cleaning provider par files {path:?}"));
-        let output = process::Command::new("make").current_dir(path)
-            .args(["clean"])
-            .output().expect("Failed to execute make binary");
+        let cargo = ProviderArchive::supports(Path::new(path));
+        let output = if cargo {
+            process::Command::new("cargo").current_dir(path)
+                .args(["clean"])
+                .tracked_output().expect("Failed to execute cargo binary")
+        } else {
+            process::Command::new("make").current_dir(path)
+                .args(["clean"])
+                .tracked_output().expect("Failed to execute make binary")
+        };
+
+        let build = Path::new(path).join("build");
+        if output.status.success() && cargo && build.exists() {
+            if let Err(error) = fs::remove_dir_all(&build) {
+                Logger::error(format!("Could not delete {:?}: {}", build, error));
+                return false;
+            }
+        }
 
         if output.status.success() {
             Logger::info("Provider par file cleaned successfully \n".into());
@@ -383,7 +615,7 @@ impl Helper {
         Logger::info(format!("Deleting directory at {path:?}"));
         let output = process::Command::new("rm")
             .args(["-rf", path])
-            .output().expect("Failed to execute rm binary");
+            .tracked_output().expect("Failed to execute rm binary");
 
         if output.status.success() {
             Logger::info(format!("Directory({path}) deleted successfully \n"));
@@ -398,7 +630,7 @@ impl Helper {
         Logger::info(format!("Stopping actor {actor_id:?}"));
         let output = process::Command::new("wash")
             .args(["stop", "actor", actor_id, "-o", "json"])
-            .output().expect("Failed to execute wash binary");
+            .tracked_output().expect("Failed to execute wash binary");
 
         if output.status.success() {
             Logger::info(format!("Actor with ID {actor_id:?} stopped \n"));
@@ -409,11 +641,16 @@ impl Helper {
         }
     }
 
-    pub fn stop_provider(provider_id: &str, contract_id: &str) {
-        Logger::info(format!("Stopping provider {provider_id:?}"));
-        let output = process::Command::new("wash")
-            .args(["stop", "provider", provider_id, contract_id, "-o", "json"])
-            .output().expect("Failed to execute wash binary");
+    /// Stops the instance of a provider with the given link name, on one host or wherever it runs.
+    /// Returns whether wash stopped it
+    pub fn stop_provider(provider_id: &str, link_name: &str, contract_id: &str, host_id: Option<&str>) -> bool {
+        Logger::info(format!("Stopping provider {provider_id:?} with link name {link_name:?}"));
+        let mut command = process::Command::new("wash");
+        command.args(["stop", "provider", provider_id, link_name, contract_id, "-o", "json"]);
+        if let Some(host_id) = host_id {
+            command.args(["--host-id", host_id]);
+        }
+        let output = command.tracked_output().expect("Failed to execute wash binary");
 
         if output.status.success() {
             Logger::info(format!("Provider with ID {provider_id:?} stopped \n"));
@@ -422,13 +659,33 @@ impl Helper {
             let error_str = String::from_utf8_lossy(&output.stderr);
             Logger::error(error_str.to_string());
         }
+        output.status.success()
     }
 
-    pub fn start_provider(image_ref: &str) {
-        Logger::info(format!("Starting provider {image_ref:?}"));
-        let output = process::Command::new("wash")
-            .args(["start", "provider", &image_ref, "-o", "json"])
-            .output().expect("Failed to execute wash binary");
+    /// Starts a provider under the given link name, passing the manifest's config to it.
+    /// Returns whether wash started it
+    pub fn start_provider(image_ref: &str, link_name: &str, host_id: Option<&str>, config: Option<&CapabilityConfig>) -> bool {
+        Logger::info(format!("Starting provider {image_ref:?} with link name {link_name:?}"));
+        let mut command = process::Command::new("wash");
+        command.args(["start", "provider", image_ref, link_name, "-o", "json"]);
+        if let Some(host_id) = host_id {
+            command.args(["--host-id", host_id]);
+        }
+
+        let config_path = state_directory().join("rendered").join(format!("provider-config-{}-{}.json", process::id(), PROVIDER_CONFIGS.fetch_add(1, Ordering::Relaxed)));
+        if let Some(config) = config {
+            let content = match config {
+                CapabilityConfig::Json(value) => value.to_string(),
+                CapabilityConfig::Opaque(value) => value.clone(),
+            };
+            if let Err(error) = fs::create_dir_all(config_path.parent().unwrap()).and_then(|_| fs::write(&config_path, content)) {
+                Logger::error(format!("Could not write the provider config to {:?}: {}", config_path, error));
+                return false;
+            }
+            command.arg("--config-json").arg(&config_path);
+        }
+        let output = command.tracked_output().expect("Failed to execute wash binary");
+        let _ = fs::remove_file(&config_path);
 
         if output.status.success() {
             Logger::info(format!("Provider at {image_ref:?} started \n"));
@@ -437,7 +694,59 @@ impl Helper {
             let error_str = String::from_utf8_lossy(&output.stderr);
             Logger::error(error_str.to_string());
         }
+        output.status.success()
+    }
+
+    /// Links an actor to a provider. Returns whether wash put the link
+    pub fn put_link(link: &LinkDefinition) -> bool {
+        Logger::info(format!("Linking actor {:?} to provider {:?} ({})", link.actor_id, link.provider_id, link.link_name));
+        let values: Vec<String> = link.values.iter().flatten().map(|(key, value)| format!("{}={}", key, value)).collect();
+        let output = process::Command::new("wash")
+            .args(["link", "put", &link.actor_id, &link.provider_id, &link.contract_id])
+            .args(&values)
+            .args(["--link-name", &link.link_name, "-o", "json"])
+            .tracked_output().expect("Failed to execute wash binary");
+
+        if !output.status.success() {
+            Logger::error(String::from_utf8_lossy(&output.stderr).to_string());
+        }
+        output.status.success()
     }
 
+    /// Every link definition of the lattice
+    pub fn get_links() -> Vec<LinkDefinition> {
+        let output = process::Command::new("wash")
+            .args(["link", "query", "-o", "json"])
+            .tracked_output().expect("Failed to execute wash binary");
+
+        if !output.status.success() {
+            Logger::error(String::from_utf8_lossy(&output.stderr).to_string());
+            return Vec::new();
+        }
+
+        let output: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap_or_default();
+        find_key(&output, &["links", "linkdefs"]).cloned()
+            .and_then(|links| serde_json::from_value(links).ok())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0).unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file() || path.with_extension("exe").is_file()
+}
 
+/// The first value under one of `keys`, searching nested objects too, since the
+/// JSON wash prints wraps wadm's responses differently between releases
+fn find_key<'a>(value: &'a serde_json::Value, keys: &[&str]) -> Option<&'a serde_json::Value> {
+    let object = value.as_object()?;
+    keys.iter()
+        .find_map(|key| object.get(*key))
+        .or_else(|| object.values().find_map(|nested| find_key(nested, keys)))
 }
@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use wadm::model::{Manifest, Properties};
+use crate::helper::Helper;
+use crate::logger::Logger;
+use crate::session::state_directory;
+
+/// The image of a component in a version that was put
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageRecord {
+    pub component: String,
+    pub image: String,
+    /// SHA-256 of a `file://` artifact when the version was put, so a rollback
+    /// can tell whether it was rebuilt since
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// A version kuwash put to wadm
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionRecord {
+    pub version: String,
+    pub put_at: u64,
+    #[serde(default)]
+    pub images: Vec<ImageRecord>,
+}
+
+/// Versions of an app kuwash put, kept in the state directory.
+///
+/// wadm stores every version but not when it was put or what the local artifacts
+/// looked like at the time, which `kuwash history` and `kuwash rollback` need.
+/// Versions put with wash directly have no record.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AppHistory {
+    pub app_name: String,
+    #[serde(default)]
+    pub versions: Vec<VersionRecord>,
+}
+
+impl AppHistory {
+    /// Directory holding history records, `$XDG_STATE_HOME/kuwash/history` on Linux
+    pub fn directory() -> PathBuf {
+        state_directory().join("history")
+    }
+
+    pub fn path(app_name: &str) -> PathBuf {
+        Self::directory().join(format!("{}.json", app_name))
+    }
+
+    /// The record of an app, empty when kuwash never put it
+    pub fn load(app_name: &str) -> AppHistory {
+        let empty = AppHistory { app_name: app_name.to_string(), versions: Vec::new() };
+        let content = match fs::read_to_string(Self::path(app_name)) {
+            Ok(content) => content,
+            Err(_) => return empty,
+        };
+
+        serde_json::from_str(&content).unwrap_or_else(|error| {
+            Logger::error(format!("Ignoring unreadable history record {:?}: {}", Self::path(app_name), error));
+            empty
+        })
+    }
+
+    /// Records a version that was just put, replacing an older record of the same version
+    pub fn record(manifest: &Manifest) {
+        let mut history = Self::load(&manifest.metadata.name);
+        let images = manifest.spec.components.iter()
+            .map(|component| {
+                let image = match &component.properties {
+                    Properties::Actor { properties } => properties.image.clone(),
+                    Properties::Capability { properties } => properties.image.clone(),
+                };
+                let sha256 = Helper::local_image_path(&image).and_then(|path| file_digest(Path::new(&path)));
+                ImageRecord { component: component.name.clone(), image, sha256 }
+            })
+            .collect();
+
+        history.versions.retain(|record| record.version != manifest.version());
+        history.versions.push(VersionRecord {
+            version: manifest.version().to_string(),
+            put_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+            images,
+        });
+        history.save();
+    }
+
+    pub fn get(&self, version: &str) -> Option<&VersionRecord> {
+        self.versions.iter().find(|record| record.version == version)
+    }
+
+    /// Writes the record to the state directory. Failures are logged but never fatal
+    fn save(&self) {
+        let path = Self::path(&self.app_name);
+        let result = fs::create_dir_all(Self::directory())
+            .and_then(|_| fs::write(&path, serde_json::to_vec_pretty(self).unwrap_or_default()));
+
+        if let Err(error) = result {
+            Logger::error(format!("Could not write history record {:?}: {}", path, error));
+        }
+    }
+}
+
+/// Hex encoded SHA-256 of a file, None when it cannot be read
+pub fn file_digest(path: &Path) -> Option<String> {
+    let content = fs::read(path).ok()?;
+    Some(format!("{:x}", Sha256::digest(&content)))
+}
@@ -0,0 +1,142 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use serde::{Deserialize, Serialize};
+use crate::logger::Logger;
+use crate::session::{is_process_alive, sanitize, state_directory};
+
+/// How often `acquire` tries to replace a missing or stale lock before giving up
+const LOCK_ATTEMPTS: usize = 3;
+
+/// Exclusive lock on an application in a lattice, held by one dev session at a time.
+///
+/// The lock is a file in the state directory keyed by lattice and app name,
+/// holding the pid of the owning session. A lock whose pid is dead is stale and
+/// gets replaced. A session can be taken over, in which case the previous owner
+/// notices through `is_held` and stops touching the lattice.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionLock {
+    pub lattice: String,
+    pub app_name: String,
+    pub pid: u32,
+    pub manifest_path: PathBuf,
+}
+
+impl SessionLock {
+    pub fn path(lattice: &str, app_name: &str) -> PathBuf {
+        state_directory()
+            .join("locks")
+            .join(sanitize(lattice))
+            .join(format!("{}.lock", sanitize(app_name)))
+    }
+
+    /// Takes the lock for this process. Returns the live owner when the lock is
+    /// held by another session, unless `take_over` is set
+    pub fn acquire(lattice: &str, app_name: &str, manifest_path: &Path, take_over: bool) -> Result<SessionLock, SessionLock> {
+        let lock = SessionLock {
+            lattice: lattice.to_string(),
+            app_name: app_name.to_string(),
+            pid: process::id(),
+            manifest_path: manifest_path.to_path_buf(),
+        };
+        let path = Self::path(lattice, app_name);
+
+        // Another session may replace a stale lock at the same time, try again when it won the race
+        for _ in 0..LOCK_ATTEMPTS {
+            if let Ok(content) = fs::read(&path) {
+                match live_owner(&content) {
+                    Some(owner) if owner.pid != lock.pid && !take_over => return Err(owner),
+                    Some(owner) => {
+                        Logger::info(format!("Taking over app {} on lattice {} from the session with pid {}", app_name, lattice, owner.pid));
+                        lock.write(false);
+                        return Ok(lock);
+                    }
+                    None => remove_stale(&path, &content),
+                }
+            }
+
+            // A missing or stale lock: linking the new one into place fails when another session got there first
+            if lock.write(true) {
+                return Ok(lock);
+            }
+        }
+
+        Self::owner(lattice, app_name)
+            .map_or_else(|| Logger::error_and_exit(format!("Could not take the lock {:?}", path)), Err)
+    }
+
+    /// The session currently holding the lock, if its process is still alive
+    pub fn owner(lattice: &str, app_name: &str) -> Option<SessionLock> {
+        live_owner(&fs::read(Self::path(lattice, app_name)).ok()?)
+    }
+
+    /// Whether this session still owns the lock, i.e. it was not taken over
+    pub fn is_held(&self) -> bool {
+        Self::owner(&self.lattice, &self.app_name)
+            .map(|owner| owner.pid == self.pid)
+            .unwrap_or(false)
+    }
+
+    /// Removes the lock file if this session still owns it
+    pub fn release(&self) -> bool {
+        if !self.is_held() {
+            return true;
+        }
+
+        match fs::remove_file(Self::path(&self.lattice, &self.app_name)) {
+            Ok(_) => true,
+            Err(error) => {
+                Logger::error(format!("Could not remove lock {:?}: {}", Self::path(&self.lattice, &self.app_name), error));
+                false
+            }
+        }
+    }
+
+    /// Writes the lock to a temporary file first, so that the lock is never seen half written,
+    /// then links it into place when `exclusive`, failing if a lock exists, or replaces the lock
+    fn write(&self, exclusive: bool) -> bool {
+        let path = Self::path(&self.lattice, &self.app_name);
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+
+        let temporary = path.with_extension(format!("lock.{}.tmp", self.pid));
+        let result = fs::write(&temporary, serde_json::to_vec_pretty(self).unwrap_or_default())
+            .and_then(|_| if exclusive { fs::hard_link(&temporary, &path) } else { fs::rename(&temporary, &path) });
+        let _ = fs::remove_file(&temporary);
+
+        match result {
+            Ok(_) => true,
+            Err(error) => {
+                if !exclusive || error.kind() != std::io::ErrorKind::AlreadyExists {
+                    Logger::error(format!("Could not write lock {:?}: {}", path, error));
+                }
+                false
+            }
+        }
+    }
+}
+
+/// The owner recorded in the content of a lock file, if its process is still alive
+fn live_owner(content: &[u8]) -> Option<SessionLock> {
+    let owner: SessionLock = serde_json::from_slice(content).ok()?;
+    if is_process_alive(owner.pid) {
+        Some(owner)
+    } else {
+        None
+    }
+}
+
+/// Removes a stale lock, but only the one found stale: it is moved aside first and put
+/// back when it turns out another session replaced it in the meantime
+fn remove_stale(path: &Path, stale: &[u8]) {
+    let aside = path.with_extension(format!("lock.{}.stale", process::id()));
+    if fs::rename(path, &aside).is_err() {
+        return;
+    }
+
+    if fs::read(&aside).map(|content| content != stale).unwrap_or(false) {
+        let _ = fs::hard_link(&aside, path);
+    }
+    let _ = fs::remove_file(&aside);
+}
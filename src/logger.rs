@@ -1,22 +1,46 @@
 use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
 use colored::*;
+use crate::progress::Progress;
+
+/// Suppresses info messages, e.g. while printing machine readable output
+static QUIET: AtomicBool = AtomicBool::new(false);
 
 pub struct Logger {}
 
 impl Logger {
-    pub fn error_and_exit(message: String) {
-        println!("{}:", "Error".red().bold());
-        println!("{} \n \n", message);
+    pub fn set_quiet(quiet: bool) {
+        QUIET.store(quiet, Ordering::SeqCst);
+    }
+
+    pub fn error_and_exit(message: String) -> ! {
+        Self::error(message);
         exit(1);
     }
 
     pub fn error(message: String) {
-        println!("{}:", "Error".red().bold());
-        println!("{} \n \n", message);
+        Progress::suspend(|| {
+            println!("{}:", "Error".red().bold());
+            println!("{} \n \n", message);
+        });
+    }
+
+    /// Something the developer should look at that does not stop kuwash, never silenced
+    pub fn warn(message: String) {
+        Progress::suspend(|| {
+            println!("{}:", "Warning".yellow().bold());
+            println!("{} \n", message);
+        });
     }
 
     pub fn info(message: String) {
-        println!("{}:", "Info".bold());
-        println!("{} \n", message);
+        if QUIET.load(Ordering::SeqCst) {
+            return;
+        }
+
+        Progress::suspend(|| {
+            println!("{}:", "Info".bold());
+            println!("{} \n", message);
+        });
     }
-}
\ No newline at end of file
+}
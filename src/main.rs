@@ -1,12 +1,43 @@
 mod arguments;
+mod claims;
 mod commands;
+mod config;
+mod diff;
 mod helper;
+mod history;
+mod lock;
 mod logger;
+mod manifest;
+mod policy;
+mod profile;
+mod progress;
+mod provider_archive;
+mod schema;
+mod session;
+mod shutdown;
+mod table;
+mod validation;
 
-use crate::arguments::Commands;
+use std::path::Path;
+use std::process::exit;
+use wadm::model::Manifest;
+use crate::arguments::{Arguments, Commands, OutputFormat};
+use crate::commands::app::AppCommand;
+use crate::commands::build::BuildCommand;
+use crate::commands::clean::CleanCommand;
 use crate::commands::dev::DevCommand;
+use crate::commands::diff::DiffCommand;
+use crate::commands::doctor::DoctorCommand;
+use crate::commands::history::HistoryCommand;
+use crate::commands::inspect::InspectCommand;
+use crate::commands::release::ReleaseCommand;
+use crate::commands::render::RenderCommand;
+use crate::commands::rollback::RollbackCommand;
+use crate::commands::status::StatusCommand;
+use crate::commands::validate::ValidateCommand;
 use crate::helper::Helper;
 use crate::logger::Logger;
+use crate::profile::{apply_local_overrides, Overlay};
 
 /// Wasmcloud is self healing when an application is deployed.
 /// The quickest way to run dev mode is to rebuild the component
@@ -15,25 +46,92 @@ use crate::logger::Logger;
 fn main() {
    let arguments = arguments::Arguments::get_arguments();
 
-   if !Helper::does_wash_cli_exist() {
-      Logger::error_and_exit("Please install wash cli to use this tool".into());
+   // doctor reports a missing wash itself, render never calls it
+   let needs_wash = !matches!(arguments.command, Commands::Doctor(_) | Commands::Render(_));
+   if needs_wash && !Helper::does_wash_cli_exist() {
+      Logger::error_and_exit("Please install wash cli to use this tool, `kuwash doctor` checks your setup".into());
+   }
+
+   // Keep stdout machine readable for commands that print YAML or JSON
+   match &arguments.command {
+      Commands::Render(_) => Logger::set_quiet(true),
+      Commands::Status(args) if args.output == OutputFormat::Json => Logger::set_quiet(true),
+      Commands::Inspect(args) if args.output == OutputFormat::Json => Logger::set_quiet(true),
+      Commands::Diff(args) if args.output == OutputFormat::Json => Logger::set_quiet(true),
+      Commands::History(args) if args.output == OutputFormat::Json => Logger::set_quiet(true),
+      _ => {}
    }
 
    match &arguments.command {
       Commands::Dev(args) => {
-         let config = Helper::get_manifest_from_wadm_config(&args.config);
+         let manifest = load_manifest(&args.config, &arguments);
          Logger::info(format!("Starting dev mode for {:?} \n\n", args.config).into());
-         match config {
-            Ok(manifest) => {
-               let mut dev = DevCommand::new(manifest, args);
-               dev.start();
-            }
-            Err(error) => {
-               Logger::error_and_exit(error.to_string());
-            }
-         }
+         let mut dev = DevCommand::new(manifest, args);
+         dev.start();
+      }
+      Commands::Clean(args) => {
+         CleanCommand::new(args).start();
+      }
+      Commands::Status(args) => {
+         StatusCommand::new(load_manifest(&args.config, &arguments), args).start();
+      }
+      Commands::Doctor(args) => {
+         DoctorCommand::new(args).start();
+      }
+      Commands::Inspect(args) => {
+         InspectCommand::new(load_manifest(&args.config, &arguments), args).start();
+      }
+      Commands::Render(args) => {
+         RenderCommand::new(load_manifest(&args.config, &arguments)).start();
+      }
+      Commands::Validate(args) => {
+         ValidateCommand::new(load_manifest(&args.config, &arguments), args, arguments.profile.clone()).start();
+      }
+      Commands::Build(args) => {
+         BuildCommand::new(load_manifest(&args.config, &arguments), args).start();
+      }
+      Commands::Diff(args) => {
+         DiffCommand::new(load_manifest(&args.config, &arguments), args).start();
+      }
+      Commands::Release(args) => {
+         ReleaseCommand::new(load_manifest(&args.config, &arguments), args).start();
+      }
+      Commands::Deploy(args) => {
+         let manifest = load_manifest(&args.config, &arguments);
+         validation::preflight(&manifest, &args.config);
+         policy::enforce(&manifest, &args.config, arguments.profile.as_deref());
+         AppCommand::new(manifest, args.force).deploy(args.build, args.yes);
+      }
+      Commands::History(args) => {
+         HistoryCommand::new(args).start();
+      }
+      Commands::Rollback(args) => {
+         RollbackCommand::new(args).start();
+      }
+      Commands::Undeploy(args) => {
+         AppCommand::new(load_manifest(&args.config, &arguments), args.force).undeploy();
+      }
+      Commands::Delete(args) => {
+         AppCommand::new(load_manifest(&args.config, &arguments), args.force).delete(args.all);
+      }
+   }
+}
 
+fn load_manifest(path: &Path, arguments: &Arguments) -> Manifest {
+   let manifest = Helper::get_manifest_from_wadm_config(path)
+      .map_err(|error| error.to_string())
+      .and_then(|mut manifest| match &arguments.profile {
+         Some(profile) => Overlay::apply_profile(&mut manifest, path, profile).map(|_| manifest),
+         None => Ok(manifest),
+      })
+      .and_then(|mut manifest| apply_local_overrides(&mut manifest, path, &arguments.local).map(|_| manifest));
 
+   match manifest {
+      Ok(manifest) => manifest,
+      // Already formatted with the location of the error in the manifest
+      Err(error) => {
+         println!("{}", error);
+         exit(1);
       }
    }
 }
@@ -0,0 +1,596 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use colored::*;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use wadm::model::{Manifest, Properties};
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::{Marker, TScalarStyle};
+use crate::schema;
+
+/// Line and column of a node in the manifest, both starting at 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// The text of a manifest together with the location of every field in it,
+/// so problems found in the parsed manifest can point back into the file
+#[derive(Debug, Clone)]
+pub struct ManifestSource {
+    pub path: PathBuf,
+    pub text: String,
+    /// Field paths such as `spec.components[0].name` to where they appear in the text
+    locations: HashMap<String, Location>,
+    /// Field paths of scalars to their value
+    values: HashMap<String, String>,
+}
+
+impl ManifestSource {
+    /// Reads the manifest and expands its `${VAR}` placeholders, see `interpolate`
+    pub fn read(path: &Path) -> Result<ManifestSource, String> {
+        let text = fs::read_to_string(path).map_err(|error| format!("Could not read {:?}: {}", path, error))?;
+        let raw = Self::new(path, text);
+        let variables = variables(&raw.env_path())?;
+
+        match interpolate(&raw.text, &variables) {
+            Ok(rendered) => Ok(Self::new(path, rendered)),
+            Err(undefined) => {
+                let hint = format!("set them in the environment or in {}, or give them a default with ${{VAR:-default}}", raw.env_path().display());
+                let errors: Vec<String> = undefined.iter()
+                    .map(|(name, location)| raw.format_error(&format!("undefined variable `{}` in the manifest", name), Some(*location)))
+                    .collect();
+                Err(format!("{}  {} {}\n", errors.join("\n"), "= help:".bold(), hint))
+            }
+        }
+    }
+
+    /// The optional `.env` file next to the manifest
+    pub fn env_path(&self) -> PathBuf {
+        self.path.parent()
+            .map(|directory| directory.join(".env"))
+            .unwrap_or(PathBuf::from(".env"))
+    }
+
+    pub fn new(path: &Path, text: String) -> ManifestSource {
+        let mut locator = Locator::default();
+        // Syntax errors are reported by `parse`, the locations found up to them are still useful
+        let _ = Parser::new(text.chars()).load(&mut locator, false);
+
+        ManifestSource {
+            path: path.to_path_buf(),
+            text,
+            locations: locator.locations,
+            values: locator.values,
+        }
+    }
+
+    /// Deserializes the manifest in any schema generation, errors point at the offending line
+    /// with a code frame. Relative `file://` images are made absolute against the manifest's directory
+    pub fn parse(&self) -> Result<Manifest, String> {
+        let mut manifest = schema::parse(self)?;
+        resolve_local_images(&mut manifest, &self.directory());
+        Ok(manifest)
+    }
+
+    /// Deserializes the text into any type, errors point at the offending line with a code frame
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, String> {
+        serde_yaml::from_str(&self.text).map_err(|error| {
+            let message = Regex::new(r" at line \d+ column \d+").unwrap().replace(&error.to_string(), "").to_string();
+            let location = error.location().map(|location| Location { line: location.line(), column: location.column() });
+            self.format_error(&message, location)
+        })
+    }
+
+    /// Absolute directory of the manifest
+    pub fn directory(&self) -> PathBuf {
+        let directory = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        directory.canonicalize().unwrap_or(directory)
+    }
+
+    /// Location of a field, or of its closest parent present in the file when the field is missing
+    pub fn locate(&self, field: &str) -> Option<Location> {
+        let mut field = field;
+        loop {
+            if let Some(location) = self.locations.get(field) {
+                return Some(*location);
+            }
+            field = &field[..field.rfind(['.', '['])?];
+        }
+    }
+
+    /// Points a field of `spec.components[N]` at the component of the same name in this file.
+    /// The index differs when the field comes from a manifest with a profile applied;
+    /// None when the component only exists in the profile's overlay
+    pub fn relocate(&self, field: &str, component: &str) -> Option<String> {
+        let captures = match Regex::new(r"^spec\.components\[(\d+)\]").unwrap().captures(field) {
+            Some(captures) => captures,
+            None => return Some(field.to_string()),
+        };
+        let name_at = |index: &str| self.values.get(&format!("spec.components[{}].name", index)).map(String::as_str);
+        if name_at(&captures[1]) == Some(component) {
+            return Some(field.to_string());
+        }
+
+        (0..).map(|index: usize| index.to_string())
+            .take_while(|index| self.locations.contains_key(&format!("spec.components[{}]", index)))
+            .find(|index| name_at(index) == Some(component))
+            .map(|index| format!("spec.components[{}]{}", index, &field[captures[0].len()..]))
+    }
+
+    /// `error: message` followed by the file position and the surrounding lines
+    pub fn format_error(&self, message: &str, location: Option<Location>) -> String {
+        let mut output = format!("{}: {}\n", "error".red().bold(), message);
+        if let Some(location) = location {
+            output.push_str(&self.code_frame(location));
+        } else {
+            output.push_str(&format!("  {} {}\n", "-->".blue().bold(), self.path.display()));
+        }
+        output
+    }
+
+    pub fn code_frame(&self, location: Location) -> String {
+        let mut lines: Vec<&str> = self.text.lines().collect();
+        // Syntax errors at the end of the file point past its last line
+        while lines.len() < location.line {
+            lines.push("");
+        }
+        let first = location.line.saturating_sub(1).max(1);
+        let last = (location.line + 1).min(lines.len());
+        let width = last.to_string().len();
+        let gutter = format!("{} |", " ".repeat(width)).blue().bold();
+
+        let mut frame = format!("{}{} {}:{}:{}\n{}\n", " ".repeat(width), "-->".blue().bold(), self.path.display(), location.line, location.column, gutter);
+        for number in first..=last {
+            frame.push_str(&format!("{} {}\n", format!("{:>width$} |", number, width = width).blue().bold(), lines[number - 1]));
+            if number == location.line {
+                frame.push_str(&format!("{} {}{}\n", gutter, " ".repeat(location.column.saturating_sub(1)), "^".red().bold()));
+            }
+        }
+        frame
+    }
+}
+
+/// Rewrites `file://./actor/build/x.wasm` style images to `file:///absolute/actor/build/x.wasm`
+/// so they do not depend on the working directory kuwash is started from
+pub fn resolve_local_images(manifest: &mut Manifest, directory: &Path) {
+    for component in manifest.spec.components.iter_mut() {
+        let image = match &mut component.properties {
+            Properties::Actor { properties } => &mut properties.image,
+            Properties::Capability { properties } => &mut properties.image,
+        };
+
+        *image = resolve_local_image(image, directory);
+    }
+}
+
+pub fn resolve_local_image(image: &str, directory: &Path) -> String {
+    match image.strip_prefix("file://") {
+        Some(path) if Path::new(path).is_relative() => format!("file://{}", normalize(&directory.join(path)).display()),
+        _ => image.to_string(),
+    }
+}
+
+/// Removes `.` and `..` from a path without touching the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Variables available to placeholders: the `.env` file, overridden by the environment.
+/// Environment variables that are not valid UTF-8 cannot be used and are skipped
+fn variables(env_path: &Path) -> Result<HashMap<String, String>, String> {
+    let mut variables = HashMap::new();
+    if env_path.exists() {
+        let entries = dotenvy::from_path_iter(env_path).map_err(|error| format!("Could not read {:?}: {}", env_path, error))?;
+        for entry in entries {
+            let (name, value) = entry.map_err(|error| format!("Could not parse {:?}: {}", env_path, error))?;
+            variables.insert(name, value);
+        }
+    }
+    variables.extend(std::env::vars_os().filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?))));
+    Ok(variables)
+}
+
+/// Expands `${VAR}` and `${VAR:-default}`, `$${VAR}` is kept as the literal `${VAR}`.
+/// Comment lines are left alone. Values are inserted as YAML scalars, never as YAML syntax:
+/// a value that would not read back as the same plain scalar turns its scalar into a double
+/// quoted string, so that e.g. `PORT='1 #x'` can neither cut a line short nor add keys.
+/// Returns every undefined variable and where it is used
+pub fn interpolate(text: &str, variables: &HashMap<String, String>) -> Result<String, Vec<(String, Location)>> {
+    let placeholder = Regex::new(r"\$?\$\{([A-Za-z_][A-Za-z0-9_]*)(:-([^}]*))?\}").unwrap();
+    let mut undefined = Vec::new();
+    let mut values = Vec::new();
+
+    // Placeholders become tokens first, parsing the tokenized text tells which scalar holds each of them
+    let lines: Vec<String> = text.split('\n').enumerate()
+        .map(|(index, line)| {
+            if line.trim_start().starts_with('#') {
+                return line.to_string();
+            }
+
+            placeholder.replace_all(line, |captures: &regex::Captures| {
+                let whole = captures.get(0).unwrap();
+                if whole.as_str().starts_with("$$") {
+                    return whole.as_str()[1..].to_string();
+                }
+
+                let name = &captures[1];
+                let value = match (variables.get(name), captures.get(3)) {
+                    (Some(value), _) => value.clone(),
+                    (None, Some(default)) => default.as_str().to_string(),
+                    (None, None) => {
+                        let column = line[..whole.start()].chars().count() + 1;
+                        undefined.push((name.to_string(), Location { line: index + 1, column }));
+                        String::new()
+                    }
+                };
+                values.push(value);
+                format!("{}{}{}", TOKEN_START, values.len() - 1, TOKEN_END)
+            }).to_string()
+        })
+        .collect();
+
+    if undefined.is_empty() {
+        Ok(insert_values(&lines.join("\n"), &values))
+    } else {
+        Err(undefined)
+    }
+}
+
+/// Start and end of a placeholder token, private use characters that are plain in any YAML scalar
+const TOKEN_START: char = '\u{E000}';
+const TOKEN_END: char = '\u{E001}';
+
+/// Replaces the tokens of `interpolate` by their values, quoting every plain or quoted
+/// scalar whose new value is not a safe plain scalar. Values in block scalars keep
+/// the indentation of their line
+fn insert_values(text: &str, values: &[String]) -> String {
+    let token = Regex::new(&format!("{}(\\d+){}", TOKEN_START, TOKEN_END)).unwrap();
+    let expand = |text: &str, indent: &str| {
+        token.replace_all(text, |captures: &regex::Captures| {
+            values[captures[1].parse::<usize>().unwrap()].replace('\n', &format!("\n{}", indent))
+        }).to_string()
+    };
+
+    let mut collector = ScalarCollector::default();
+    if Parser::new(text.chars()).load(&mut collector, false).is_err() {
+        // The syntax error is reported when the manifest is parsed
+        return expand(text, "");
+    }
+
+    let mut chars: Vec<char> = text.chars().collect();
+    // From the last scalar on, so the positions of the earlier ones stay valid
+    for scalar in collector.scalars.iter().rev().filter(|scalar| scalar.value.contains(TOKEN_START)) {
+        let value = expand(&scalar.value, "");
+        let (end, replacement) = match scalar.style {
+            TScalarStyle::Plain => {
+                let end = scalar.start + scalar.value.chars().count();
+                // Plain scalars spanning several lines are folded, their text is not their value
+                if chars.get(scalar.start..end).map(|raw| raw.iter().collect::<String>()) != Some(scalar.value.clone()) {
+                    continue;
+                }
+                (end, if is_plain_safe(&value) { value } else { serde_json::to_string(&value).unwrap() })
+            }
+            TScalarStyle::SingleQuoted | TScalarStyle::DoubleQuoted => (quoted_end(&chars, scalar.start), serde_json::to_string(&value).unwrap()),
+            _ => continue,
+        };
+        chars.splice(scalar.start..end, replacement.chars());
+    }
+
+    chars.into_iter().collect::<String>()
+        .split('\n')
+        .map(|line| expand(line, &line[..line.len() - line.trim_start().len()]))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Whether a value reads back as the same plain scalar wherever a plain scalar may stand,
+/// in block and flow collections alike. Empty values stay empty, i.e. null
+fn is_plain_safe(value: &str) -> bool {
+    let first = match value.chars().next() {
+        Some(first) => first,
+        None => return true,
+    };
+    let indicator = "?:,[]{}#&*!|>'\"%@`".contains(first) || (first == '-' && matches!(value.chars().nth(1), None | Some(' ')));
+
+    !indicator
+        && value.trim() == value
+        && !value.chars().any(|c| c.is_control() || ",[]{}".contains(c))
+        && !value.contains(": ")
+        && !value.contains(" #")
+        && !value.ends_with(':')
+}
+
+/// Position after the closing quote of the quoted scalar starting at `start`
+fn quoted_end(chars: &[char], start: usize) -> usize {
+    let quote = chars[start];
+    let mut index = start + 1;
+    while index < chars.len() {
+        match chars[index] {
+            '\\' if quote == '"' => index += 2,
+            '\'' if quote == '\'' && chars.get(index + 1) == Some(&'\'') => index += 2,
+            c if c == quote => return index + 1,
+            _ => index += 1,
+        }
+    }
+    chars.len()
+}
+
+/// The name closest to `name`, if it is close enough to be a likely typo
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(2);
+    candidates.into_iter()
+        .map(|candidate| (strsim::levenshtein(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+enum Frame {
+    Mapping { path: String, key: Option<String> },
+    Sequence { path: String, index: usize },
+}
+
+/// A scalar of the YAML text, `start` is its first character, the opening quote of a quoted scalar
+struct Scalar {
+    start: usize,
+    style: TScalarStyle,
+    value: String,
+}
+
+/// Records every scalar, keys included, while the YAML is parsed
+#[derive(Default)]
+struct ScalarCollector {
+    scalars: Vec<Scalar>,
+}
+
+impl MarkedEventReceiver for ScalarCollector {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        if let Event::Scalar(value, style, ..) = event {
+            self.scalars.push(Scalar { start: mark.index(), style, value });
+        }
+    }
+}
+
+/// Records the location of every node while the YAML is parsed
+#[derive(Default)]
+struct Locator {
+    stack: Vec<Frame>,
+    locations: HashMap<String, Location>,
+    values: HashMap<String, String>,
+}
+
+impl Locator {
+    /// Path of the node that starts now, None when the node is a mapping key
+    fn node_path(&mut self, event: &Event, mark: &Marker) -> Option<String> {
+        let location = Location { line: mark.line(), column: mark.col() + 1 };
+        match self.stack.last_mut() {
+            None => Some(String::new()),
+            Some(Frame::Sequence { path, index }) => {
+                let node = format!("{}[{}]", path, index);
+                *index += 1;
+                self.locations.insert(node.clone(), location);
+                Some(node)
+            }
+            Some(Frame::Mapping { path, key }) => match key.take() {
+                None => {
+                    // A key, point its field at it until a scalar value is found
+                    let name = match event {
+                        Event::Scalar(name, ..) => name.clone(),
+                        _ => String::new(),
+                    };
+                    let node = if path.is_empty() { name.clone() } else { format!("{}.{}", path, name) };
+                    self.locations.insert(node, location);
+                    *key = Some(name);
+                    None
+                }
+                Some(name) => {
+                    let node = if path.is_empty() { name } else { format!("{}.{}", path, name) };
+                    if let Event::Scalar(..) = event {
+                        self.locations.insert(node.clone(), location);
+                    }
+                    Some(node)
+                }
+            },
+        }
+    }
+}
+
+impl MarkedEventReceiver for Locator {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(..) | Event::Alias(_) => {
+                if let (Some(path), Event::Scalar(value, ..)) = (self.node_path(&event, &mark), &event) {
+                    self.values.insert(path, value.clone());
+                }
+            }
+            // Complex keys get a path no field will ever ask for
+            Event::MappingStart(_) => {
+                let path = self.node_path(&event, &mark).unwrap_or_else(|| "?".to_string());
+                self.stack.push(Frame::Mapping { path, key: None });
+            }
+            Event::SequenceStart(_) => {
+                let path = self.node_path(&event, &mark).unwrap_or_else(|| "?".to_string());
+                self.stack.push(Frame::Sequence { path, index: 0 });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(text: &str, variables: &[(&str, &str)]) -> String {
+        let variables = variables.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        interpolate(text, &variables).unwrap()
+    }
+
+    fn parse(text: &str) -> yaml_rust::Yaml {
+        yaml_rust::YamlLoader::load_from_str(text).unwrap().remove(0)
+    }
+
+    #[test]
+    fn expands_variables_and_defaults() {
+        let text = "name: ${NAME}\nport: ${PORT:-8080}\nhost: ${HOST:-}\n";
+        assert_eq!(expand(text, &[("NAME", "echo")]), "name: echo\nport: 8080\nhost: \n");
+    }
+
+    #[test]
+    fn keeps_escaped_placeholders_and_comment_lines() {
+        let text = "# ${MISSING}\nvalue: $${NAME}\n";
+        assert_eq!(expand(text, &[]), "# ${MISSING}\nvalue: ${NAME}\n");
+    }
+
+    #[test]
+    fn reports_undefined_variables_where_they_are_used() {
+        let undefined = interpolate("a: 1\nb: x-${FIRST}\nc: ${SECOND}\n", &HashMap::new()).unwrap_err();
+        let names: Vec<_> = undefined.iter().map(|(name, location)| (name.as_str(), location.line, location.column)).collect();
+        assert_eq!(names, vec![("FIRST", 2, 6), ("SECOND", 3, 4)]);
+    }
+
+    #[test]
+    fn keeps_plain_values_plain() {
+        let yaml = parse(&expand("replicas: ${REPLICAS}\nimage: ${IMAGE}\n", &[("REPLICAS", "3"), ("IMAGE", "file://./build/echo_s.wasm")]));
+        assert_eq!(yaml["replicas"].as_i64(), Some(3));
+        assert_eq!(yaml["image"].as_str(), Some("file://./build/echo_s.wasm"));
+    }
+
+    #[test]
+    fn quotes_values_that_would_change_the_document() {
+        let text = "address: 0.0.0.0:${PORT}\nname: ${NAME}\nlist: [${ITEM}]\n";
+        let yaml = parse(&expand(text, &[("PORT", "1 #x"), ("NAME", "a: b\nc: d"), ("ITEM", "x, y")]));
+        assert_eq!(yaml["address"].as_str(), Some("0.0.0.0:1 #x"));
+        assert_eq!(yaml["name"].as_str(), Some("a: b\nc: d"));
+        assert_eq!(yaml["list"].as_vec().map(Vec::len), Some(1));
+        assert_eq!(yaml["list"][0].as_str(), Some("x, y"));
+        assert!(yaml["c"].is_badvalue());
+    }
+
+    #[test]
+    fn escapes_values_inside_quoted_scalars() {
+        let text = "double: \"say \\\"${WORD}\\\"\"\nsingle: 'it''s ${WORD}'\n";
+        let yaml = parse(&expand(text, &[("WORD", "\"hi\"\n")]));
+        assert_eq!(yaml["double"].as_str(), Some("say \"\"hi\"\n\""));
+        assert_eq!(yaml["single"].as_str(), Some("it's \"hi\"\n"));
+    }
+
+    #[test]
+    fn indents_values_in_block_scalars() {
+        let text = "config: |\n  first\n  ${LINES}\nnext: 1\n";
+        let yaml = parse(&expand(text, &[("LINES", "second\nthird")]));
+        assert_eq!(yaml["config"].as_str(), Some("first\nsecond\nthird\n"));
+        assert_eq!(yaml["next"].as_i64(), Some(1));
+    }
+
+    #[test]
+    fn judges_plain_scalars() {
+        for safe in ["", "echo", "0.0.0.0:8080", "-1", "a#b", "wasmcloud:httpserver"] {
+            assert!(is_plain_safe(safe), "{:?}", safe);
+        }
+        for unsafe_value in [" a", "a ", "- a", "-", "*alias", "a: b", "a #b", "a:", "[a]", "a,b", "a\nb", "'a'", "#a"] {
+            assert!(!is_plain_safe(unsafe_value), "{:?}", unsafe_value);
+        }
+    }
+
+    const MANIFEST: &str = "apiVersion: core.oam.dev/v1beta1
+kind: Application
+metadata:
+  name: echo
+spec:
+  components:
+    - name: echo
+      type: actor
+      properties:
+        image: file:///echo.wasm
+    - name: httpserver
+      type: capability
+      properties: { image: file:///httpserver.par.gz, contract: wasmcloud:httpserver }
+";
+
+    fn source(text: &str) -> ManifestSource {
+        ManifestSource::new(Path::new("wadm.yaml"), text.to_string())
+    }
+
+    #[test]
+    fn locates_fields_in_block_and_flow_style() {
+        let source = source(MANIFEST);
+        assert_eq!(source.locate("metadata.name"), Some(Location { line: 4, column: 9 }));
+        assert_eq!(source.locate("spec.components[0]").map(|location| location.line), Some(7));
+        assert_eq!(source.locate("spec.components[0].properties.image"), Some(Location { line: 10, column: 16 }));
+        assert_eq!(source.locate("spec.components[1].properties.contract"), Some(Location { line: 13, column: 65 }));
+    }
+
+    #[test]
+    fn locates_missing_fields_at_their_closest_parent() {
+        let source = source(MANIFEST);
+        assert_eq!(source.locate("spec.components[0].traits[0].properties"), source.locate("spec.components[0]"));
+        assert_eq!(source.locate("spec.components[5].name"), source.locate("spec.components"));
+        assert_eq!(source.locate("status"), None);
+    }
+
+    #[test]
+    fn relocates_fields_to_the_component_of_the_same_name() {
+        let source = source(MANIFEST);
+        assert_eq!(source.relocate("spec.components[0].properties.image", "echo").as_deref(), Some("spec.components[0].properties.image"));
+        assert_eq!(source.relocate("spec.components[0].properties.image", "httpserver").as_deref(), Some("spec.components[1].properties.image"));
+        assert_eq!(source.relocate("spec.components[2].name", "overlay"), None);
+        assert_eq!(source.relocate("metadata.name", "echo").as_deref(), Some("metadata.name"));
+    }
+
+    #[test]
+    fn keeps_the_locations_found_before_a_syntax_error() {
+        let source = source("metadata:\n  name: echo\nspec: [\n");
+        assert_eq!(source.locate("metadata.name"), Some(Location { line: 2, column: 9 }));
+        assert!(source.deserialize::<serde_yaml::Value>().is_err());
+    }
+
+    #[test]
+    fn frames_the_lines_around_a_location() {
+        colored::control::set_override(false);
+        let frame = source(MANIFEST).code_frame(Location { line: 10, column: 16 });
+        assert_eq!(frame, concat!(
+            "  --> wadm.yaml:10:16\n",
+            "   |\n",
+            " 9 |       properties:\n",
+            "10 |         image: file:///echo.wasm\n",
+            "   |                ^\n",
+            "11 |     - name: httpserver\n",
+        ));
+    }
+
+    #[test]
+    fn frames_locations_past_the_last_line() {
+        colored::control::set_override(false);
+        let frame = source("a: 1\nb: [").code_frame(Location { line: 3, column: 1 });
+        assert_eq!(frame, " --> wadm.yaml:3:1\n  |\n2 | b: [\n3 | \n  | ^\n");
+    }
+
+    #[test]
+    fn points_deserialize_errors_at_their_line() {
+        colored::control::set_override(false);
+        let error = source("kind: [\n  - a\n").deserialize::<Manifest>().unwrap_err();
+        assert!(error.starts_with("error: "), "{}", error);
+        assert!(error.contains("--> wadm.yaml:"), "{}", error);
+        assert!(!error.contains(" at line "), "{}", error);
+    }
+}
@@ -0,0 +1,473 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use serde::Deserialize;
+use wadm::model::{Manifest, Properties, TraitProperty, DAEMONSCALER_TRAIT, LATEST_VERSION, SPREADSCALER_TRAIT};
+use crate::config::ProjectConfig;
+use crate::claims::ComponentClaims;
+use crate::helper::Helper;
+use crate::manifest::ManifestSource;
+use crate::schema;
+use crate::validation::{print_problems, Problem};
+
+/// Name of the policy file looked up next to the manifest, unless `policy` in `kuwash.yaml` points elsewhere
+pub const POLICY_FILE: &str = "kuwash.policy.yaml";
+
+/// Guardrails a project puts on its manifests, checked by `validate` and `deploy`.
+/// Every rule is off until it is configured
+///
+/// ```yaml
+/// noLocalImages: true
+/// devProfiles: [dev]
+/// noLatestTags: true
+/// maxReplicas: 10
+/// requiredAnnotations: [version, description]
+/// allowedContracts: [wasmcloud:httpserver, wasmcloud:keyvalue]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Policy {
+    /// Forbids `file://` images outside of the dev profiles
+    #[serde(default)]
+    pub no_local_images: bool,
+    /// Profiles `file://` images are allowed in. Running without a profile counts as `dev`
+    #[serde(default = "default_dev_profiles")]
+    pub dev_profiles: Vec<String>,
+    /// Forbids images tagged `latest` or not tagged at all
+    #[serde(default)]
+    pub no_latest_tags: bool,
+    /// Most replicas a scaler trait may ask for
+    pub max_replicas: Option<usize>,
+    /// Annotations every manifest must carry
+    #[serde(default)]
+    pub required_annotations: Vec<String>,
+    /// Contracts capabilities may provide and actors may be signed for, any when empty
+    #[serde(default)]
+    pub allowed_contracts: Vec<String>,
+}
+
+fn default_dev_profiles() -> Vec<String> {
+    vec!["dev".to_string()]
+}
+
+/// What a rule looks at: the manifest, the profile it was loaded with and the claims of its built actors
+pub struct Context<'a> {
+    pub manifest: &'a Manifest,
+    pub profile: &'a str,
+    pub claims: HashMap<String, ComponentClaims>,
+}
+
+/// A single check of the policy. Its violations are problems reported under the rule's ID
+pub trait Rule {
+    fn id(&self) -> &'static str;
+    fn check(&self, context: &Context) -> Vec<Problem>;
+}
+
+impl Policy {
+    /// The policy file of the project the manifest belongs to
+    pub fn path(manifest_path: &Path) -> PathBuf {
+        let directory = manifest_path.parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+
+        match ProjectConfig::load(manifest_path).policy {
+            Some(path) => directory.join(path),
+            None => directory.join(POLICY_FILE),
+        }
+    }
+
+    /// The project's policy, None when it has none
+    pub fn load(manifest_path: &Path) -> Result<Option<Policy>, String> {
+        let path = Self::path(manifest_path);
+        if !path.exists() {
+            if ProjectConfig::load(manifest_path).policy.is_some() {
+                return Err(format!("The policy file {:?} set in kuwash.yaml does not exist", path));
+            }
+            return Ok(None);
+        }
+
+        ManifestSource::read(&path)?.deserialize().map(Some)
+    }
+
+    pub fn rules(&self) -> Vec<Box<dyn Rule>> {
+        let mut rules: Vec<Box<dyn Rule>> = Vec::new();
+        if self.no_local_images {
+            rules.push(Box::new(NoLocalImages { dev_profiles: self.dev_profiles.clone() }));
+        }
+        if self.no_latest_tags {
+            rules.push(Box::new(NoLatestTags));
+        }
+        if let Some(max) = self.max_replicas {
+            rules.push(Box::new(MaxReplicas { max }));
+        }
+        if !self.required_annotations.is_empty() {
+            rules.push(Box::new(RequiredAnnotations { keys: self.required_annotations.clone() }));
+        }
+        if !self.allowed_contracts.is_empty() {
+            rules.push(Box::new(AllowedContracts { contracts: self.allowed_contracts.clone() }));
+        }
+        rules
+    }
+
+    /// Every violation of the policy, `profile` is the one the manifest was loaded with
+    pub fn evaluate(&self, manifest: &Manifest, profile: Option<&str>) -> Vec<Problem> {
+        let context = Context {
+            manifest,
+            profile: profile.unwrap_or("dev"),
+            claims: local_claims(manifest),
+        };
+
+        self.rules().iter().flat_map(|rule| rule.check(&context)).collect()
+    }
+}
+
+/// Violations of the project's policy, exits when the policy file cannot be read
+pub fn check_policy(manifest: &Manifest, manifest_path: &Path, profile: Option<&str>) -> Vec<Problem> {
+    match Policy::load(manifest_path) {
+        Ok(Some(policy)) => policy.evaluate(manifest, profile),
+        Ok(None) => Vec::new(),
+        Err(error) => {
+            println!("{}", error);
+            exit(1);
+        }
+    }
+}
+
+/// Prints every violation of the project's policy and exits when there are any
+pub fn enforce(manifest: &Manifest, manifest_path: &Path, profile: Option<&str>) {
+    let problems = check_policy(manifest, manifest_path, profile);
+    if problems.is_empty() {
+        return;
+    }
+
+    print_problems(&problems, manifest_path);
+    println!("{} policy violation(s) in {:?}, see {:?}", problems.len(), manifest_path, Policy::path(manifest_path));
+    exit(1);
+}
+
+/// Claims of the actors whose local artifact is already built
+fn local_claims(manifest: &Manifest) -> HashMap<String, ComponentClaims> {
+    manifest.spec.components.iter()
+        .filter_map(|component| match &component.properties {
+            Properties::Actor { properties } => Helper::local_image_path(&properties.image)
+                .filter(|path| Path::new(path).exists())
+                .and_then(|path| Helper::try_inspect_image(&path, &schema::component_id(manifest, component)).ok())
+                .map(|claims| (component.name.clone(), claims)),
+            Properties::Capability { .. } => None,
+        })
+        .collect()
+}
+
+fn image(properties: &Properties) -> &str {
+    match properties {
+        Properties::Actor { properties } => &properties.image,
+        Properties::Capability { properties } => &properties.image,
+    }
+}
+
+struct NoLocalImages {
+    dev_profiles: Vec<String>,
+}
+
+impl Rule for NoLocalImages {
+    fn id(&self) -> &'static str {
+        "no-local-images"
+    }
+
+    fn check(&self, context: &Context) -> Vec<Problem> {
+        if self.dev_profiles.iter().any(|profile| profile == context.profile) {
+            return Vec::new();
+        }
+
+        context.manifest.spec.components.iter().enumerate()
+            .filter(|(_, component)| Helper::local_image_path(image(&component.properties)).is_some())
+            .map(|(index, component)| Problem::new(
+                self.id(), Some(component), format!("spec.components[{}].properties.image", index),
+                format!("the local image {} cannot be used in the {} profile", image(&component.properties), context.profile),
+            ).with_hint(Some("push it with `kuwash release` and use the released manifest".to_string())))
+            .collect()
+    }
+}
+
+struct NoLatestTags;
+
+impl Rule for NoLatestTags {
+    fn id(&self) -> &'static str {
+        "no-latest-tag"
+    }
+
+    fn check(&self, context: &Context) -> Vec<Problem> {
+        context.manifest.spec.components.iter().enumerate()
+            .filter(|(_, component)| is_latest(image(&component.properties)))
+            .map(|(index, component)| Problem::new(
+                self.id(), Some(component), format!("spec.components[{}].properties.image", index),
+                format!("{} is not pinned to a version", image(&component.properties)),
+            ))
+            .collect()
+    }
+}
+
+/// An OCI reference without a digest whose tag is `latest` or missing
+fn is_latest(image: &str) -> bool {
+    if image.starts_with("file://") || image.contains('@') {
+        return false;
+    }
+
+    let name = image.rsplit('/').next().unwrap_or(image);
+    match name.split_once(':') {
+        Some((_, tag)) => tag == LATEST_VERSION,
+        None => true,
+    }
+}
+
+struct MaxReplicas {
+    max: usize,
+}
+
+impl Rule for MaxReplicas {
+    fn id(&self) -> &'static str {
+        "max-replicas"
+    }
+
+    fn check(&self, context: &Context) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        for (index, component) in context.manifest.spec.components.iter().enumerate() {
+            for (trait_index, t) in component.traits.iter().flatten().enumerate() {
+                match &t.properties {
+                    TraitProperty::SpreadScaler(props) if (t.trait_type == SPREADSCALER_TRAIT || t.trait_type == DAEMONSCALER_TRAIT) && props.replicas > self.max => {
+                        problems.push(Problem::new(
+                            self.id(), Some(component), format!("spec.components[{}].traits[{}].properties.replicas", index, trait_index),
+                            format!("{} replicas exceed the maximum of {}", props.replicas, self.max),
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        problems
+    }
+}
+
+struct RequiredAnnotations {
+    keys: Vec<String>,
+}
+
+impl Rule for RequiredAnnotations {
+    fn id(&self) -> &'static str {
+        "required-annotation"
+    }
+
+    fn check(&self, context: &Context) -> Vec<Problem> {
+        let annotations = &context.manifest.metadata.annotations;
+        self.keys.iter()
+            .filter(|key| annotations.get(*key).map(|value| value.trim().is_empty()).unwrap_or(true))
+            .map(|key| Problem::new(self.id(), None, format!("metadata.annotations.{}", key), format!("the manifest has no `{}` annotation", key)))
+            .collect()
+    }
+}
+
+struct AllowedContracts {
+    contracts: Vec<String>,
+}
+
+impl Rule for AllowedContracts {
+    fn id(&self) -> &'static str {
+        "allowed-contracts"
+    }
+
+    fn check(&self, context: &Context) -> Vec<Problem> {
+        let hint = Some(format!("allowed contracts: {}", self.contracts.join(", ")));
+        let mut problems = Vec::new();
+        for (index, component) in context.manifest.spec.components.iter().enumerate() {
+            let (field, contracts) = match (&component.properties, context.claims.get(&component.name)) {
+                // Capabilities of the components schema whose links name no interface have no contract to check
+                (Properties::Capability { properties }, _) if properties.contract.is_empty() => continue,
+                (Properties::Capability { properties }, _) => ("contract", vec![properties.contract.clone()]),
+                (Properties::Actor { .. }, Some(ComponentClaims::Actor(claims))) => ("image", claims.capabilities.clone()),
+                _ => continue,
+            };
+
+            for contract in contracts.iter().filter(|contract| !self.contracts.contains(contract)) {
+                let message = match field {
+                    "contract" => format!("the contract {} is not allowed", contract),
+                    _ => format!("the actor is signed for {} which is not allowed", contract),
+                };
+                problems.push(Problem::new(self.id(), Some(component), format!("spec.components[{}].properties.{}", index, field), message).with_hint(hint.clone()));
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::claims::ActorClaims;
+    use super::*;
+
+    const MANIFEST: &str = "
+apiVersion: core.oam.dev/v1beta1
+kind: Application
+metadata:
+  name: echo
+  annotations:
+    version: v0.0.1
+    description: ' '
+spec:
+  components:
+    - name: echo
+      type: actor
+      properties:
+        image: file:///echo/build/echo_s.wasm
+      traits:
+        - type: spreadscaler
+          properties:
+            replicas: 20
+    - name: httpserver
+      type: capability
+      properties:
+        image: wasmcloud.azurecr.io/httpserver:0.17.0
+        contract: wasmcloud:httpserver
+      traits:
+        - type: linkdef
+          properties:
+            target: echo
+        - type: daemonscaler
+          properties:
+            replicas: 5
+    - name: keyvalue
+      type: capability
+      properties:
+        image: wasmcloud.azurecr.io/kvredis
+        contract: wasmcloud:keyvalue
+";
+
+    fn policy(text: &str) -> Policy {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    fn check(rule: &dyn Rule, profile: &str, claims: HashMap<String, ComponentClaims>) -> Vec<(String, String)> {
+        let manifest: Manifest = serde_yaml::from_str(MANIFEST).unwrap();
+        let context = Context { manifest: &manifest, profile, claims };
+        rule.check(&context).into_iter()
+            .inspect(|problem| assert_eq!(problem.code, rule.id()))
+            .map(|problem| (problem.field, problem.message))
+            .collect()
+    }
+
+    fn fields(problems: &[(String, String)]) -> Vec<&str> {
+        problems.iter().map(|(field, _)| field.as_str()).collect()
+    }
+
+    #[test]
+    fn enables_only_the_configured_rules() {
+        assert!(policy("{}").rules().is_empty());
+        assert_eq!(policy("{}").dev_profiles, vec!["dev"]);
+
+        let ids: Vec<_> = policy("noLocalImages: true\nnoLatestTags: true\nmaxReplicas: 3\nrequiredAnnotations: [version]\nallowedContracts: [wasmcloud:httpserver]\n")
+            .rules().iter().map(|rule| rule.id()).collect();
+        assert_eq!(ids, vec!["no-local-images", "no-latest-tag", "max-replicas", "required-annotation", "allowed-contracts"]);
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        assert!(serde_yaml::from_str::<Policy>("noLatestTag: true\n").is_err());
+    }
+
+    #[test]
+    fn allows_local_images_only_in_dev_profiles() {
+        let rule = NoLocalImages { dev_profiles: vec!["dev".to_string(), "local".to_string()] };
+        assert!(check(&rule, "dev", HashMap::new()).is_empty());
+        assert!(check(&rule, "local", HashMap::new()).is_empty());
+        assert_eq!(fields(&check(&rule, "prod", HashMap::new())), vec!["spec.components[0].properties.image"]);
+    }
+
+    #[test]
+    fn tells_unpinned_images() {
+        for image in ["wasmcloud.azurecr.io/kvredis", "wasmcloud.azurecr.io/kvredis:latest", "localhost:5000/echo", "echo"] {
+            assert!(is_latest(image), "{}", image);
+        }
+        for image in ["wasmcloud.azurecr.io/kvredis:0.22.0", "localhost:5000/echo:1.0", "echo@sha256:abc", "file:///echo.wasm"] {
+            assert!(!is_latest(image), "{}", image);
+        }
+        assert_eq!(fields(&check(&NoLatestTags, "dev", HashMap::new())), vec!["spec.components[2].properties.image"]);
+    }
+
+    #[test]
+    fn limits_the_replicas_of_every_scaler() {
+        let problems = check(&MaxReplicas { max: 4 }, "dev", HashMap::new());
+        assert_eq!(fields(&problems), vec!["spec.components[0].traits[0].properties.replicas", "spec.components[1].traits[1].properties.replicas"]);
+        assert_eq!(problems[0].1, "20 replicas exceed the maximum of 4");
+        assert!(check(&MaxReplicas { max: 20 }, "dev", HashMap::new()).is_empty());
+    }
+
+    #[test]
+    fn requires_annotations_with_a_value() {
+        let rule = RequiredAnnotations { keys: vec!["version".to_string(), "description".to_string(), "owner".to_string()] };
+        assert_eq!(fields(&check(&rule, "dev", HashMap::new())), vec!["metadata.annotations.description", "metadata.annotations.owner"]);
+    }
+
+    #[test]
+    fn allows_only_the_listed_contracts() {
+        let rule = AllowedContracts { contracts: vec!["wasmcloud:httpserver".to_string()] };
+        let claims = HashMap::from([("echo".to_string(), ComponentClaims::Actor(ActorClaims {
+            public_key: "MECHO".to_string(),
+            issuer: "AISSUER".to_string(),
+            name: None,
+            capabilities: vec!["wasmcloud:httpserver".to_string(), "wasmcloud:messaging".to_string()],
+            tags: Vec::new(),
+            revision: None,
+            version: None,
+            call_alias: None,
+        }))]);
+
+        let problems = check(&rule, "dev", claims);
+        assert_eq!(problems, vec![
+            ("spec.components[0].properties.image".to_string(), "the actor is signed for wasmcloud:messaging which is not allowed".to_string()),
+            ("spec.components[2].properties.contract".to_string(), "the contract wasmcloud:keyvalue is not allowed".to_string()),
+        ]);
+        // Actors not built yet cannot be checked
+        assert_eq!(check(&rule, "dev", HashMap::new()).len(), 1);
+    }
+
+    #[test]
+    fn skips_capabilities_without_a_contract() {
+        let manifest = ManifestSource::new(Path::new("wadm.yaml"), "
+apiVersion: core.oam.dev/v1beta1
+kind: Application
+metadata:
+  name: hello
+  annotations:
+    version: v0.0.1
+spec:
+  components:
+    - name: http-component
+      type: component
+      properties:
+        image: ghcr.io/wasmcloud/components/http-hello-world-rust:0.1.0
+    - name: httpserver
+      type: capability
+      properties:
+        image: ghcr.io/wasmcloud/http-server:0.20.0
+      traits:
+        - type: link
+          properties:
+            target: http-component
+            interfaces: [incoming-handler]
+    - name: keyvalue
+      type: capability
+      properties:
+        image: ghcr.io/wasmcloud/keyvalue-redis:0.24.0
+      traits:
+        - type: link
+          properties:
+            target: http-component
+            namespace: wasi
+            package: keyvalue
+            interfaces: [store]
+".to_string()).parse().unwrap();
+        let rule = AllowedContracts { contracts: vec!["wasi:http".to_string()] };
+        let problems = rule.check(&Context { manifest: &manifest, profile: "dev", claims: HashMap::new() });
+        let messages: Vec<_> = problems.iter().map(|problem| (problem.component.as_deref(), problem.message.as_str())).collect();
+        assert_eq!(messages, vec![(Some("keyvalue"), "the contract wasi:keyvalue is not allowed")]);
+    }
+}
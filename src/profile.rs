@@ -0,0 +1,240 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use colored::*;
+use serde::Deserialize;
+use wadm::model::{Component, LinkdefProperty, Manifest, Properties, SpreadScalerProperty, Trait, TraitProperty, DAEMONSCALER_TRAIT, LINKDEF_TRAIT, SPREADSCALER_TRAIT};
+use crate::config::ProjectConfig;
+use crate::helper::Helper;
+use crate::logger::Logger;
+use crate::manifest::{resolve_local_image, suggest, ManifestSource};
+
+/// Changes a profile makes to the base manifest, e.g. `wadm.staging.yaml`
+///
+/// ```yaml
+/// annotations:
+///   version: v0.2.0-staging
+/// remove: [debug-logger]
+/// components:
+///   - name: echo
+///     image: registry.example.com/echo:0.2.0
+///     replicas: 3
+///     links:
+///       httpserver:
+///         address: 0.0.0.0:80
+/// add:
+///   - name: keyvalue
+///     type: capability
+///     properties: { image: ..., contract: wasmcloud:keyvalue }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Overlay {
+    /// Merged into the annotations of the manifest
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+    /// Names of components to drop from the manifest
+    #[serde(default)]
+    pub remove: Vec<String>,
+    #[serde(default)]
+    pub components: Vec<ComponentOverlay>,
+    /// Complete components appended to the manifest
+    #[serde(default)]
+    pub add: Vec<Component>,
+}
+
+/// Changes to a single component of the base manifest, matched by name
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ComponentOverlay {
+    pub name: String,
+    pub image: Option<String>,
+    /// Replaces the replicas of every scaler trait, adds a spreadscaler when there is none
+    pub replicas: Option<usize>,
+    /// Values merged into the linkdef to each target, a null value removes the key
+    #[serde(default)]
+    pub links: BTreeMap<String, HashMap<String, Option<String>>>,
+}
+
+impl Overlay {
+    /// The overlay file of a profile: the path configured under `profiles` in
+    /// `kuwash.yaml`, otherwise `<manifest>.<profile>.yaml` next to the manifest
+    pub fn path(manifest_path: &Path, profile: &str) -> PathBuf {
+        let directory = manifest_path.parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+
+        if let Some(path) = ProjectConfig::load(manifest_path).profiles.get(profile) {
+            return directory.join(path);
+        }
+
+        let stem = manifest_path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or("wadm".to_string());
+        let extension = manifest_path.extension().map(|extension| extension.to_string_lossy().to_string()).unwrap_or("yaml".to_string());
+        directory.join(format!("{}.{}.{}", stem, profile, extension))
+    }
+
+    /// Applies the overlay of `profile` to the manifest loaded from `manifest_path`
+    pub fn apply_profile(manifest: &mut Manifest, manifest_path: &Path, profile: &str) -> Result<(), String> {
+        let path = Self::path(manifest_path, profile);
+        if !path.exists() {
+            return Err(format!("{}: profile {:?} has no overlay, expected {:?}. Create it or point `profiles.{}` in kuwash.yaml at it\n", "error".red().bold(), profile, path, profile));
+        }
+
+        let source = ManifestSource::read(&path)?;
+        let mut overlay: Overlay = source.deserialize()?;
+        overlay.resolve_local_images(&source.directory());
+        overlay.apply(manifest, &source)
+    }
+
+    /// Relative `file://` images of the overlay are relative to the overlay file
+    fn resolve_local_images(&mut self, directory: &Path) {
+        for component in self.add.iter_mut() {
+            match &mut component.properties {
+                Properties::Actor { properties } => properties.image = resolve_local_image(&properties.image, directory),
+                Properties::Capability { properties } => properties.image = resolve_local_image(&properties.image, directory),
+            }
+        }
+
+        for component in self.components.iter_mut() {
+            component.image = component.image.as_ref().map(|image| resolve_local_image(image, directory));
+        }
+    }
+
+    /// Merges the overlay into the manifest. Names that match no component are
+    /// errors pointing into the overlay file
+    pub fn apply(&self, manifest: &mut Manifest, source: &ManifestSource) -> Result<(), String> {
+        let mut errors = Vec::new();
+        let unknown = |name: &str, field: String, manifest: &Manifest| {
+            let names = manifest.spec.components.iter().map(|component| component.name.as_str());
+            let hint = suggest(name, names).map(|candidate| format!(", did you mean {:?}?", candidate)).unwrap_or_default();
+            source.format_error(&format!("the component {:?} is not in the manifest{}", name, hint), source.locate(&field))
+        };
+
+        manifest.metadata.annotations.extend(self.annotations.clone());
+
+        for (index, name) in self.remove.iter().enumerate() {
+            let before = manifest.spec.components.len();
+            manifest.spec.components.retain(|component| &component.name != name);
+            if manifest.spec.components.len() == before {
+                errors.push(unknown(name, format!("remove[{}]", index), manifest));
+            }
+        }
+
+        for (index, patch) in self.components.iter().enumerate() {
+            match manifest.spec.components.iter_mut().find(|component| component.name == patch.name) {
+                Some(component) => patch.apply(component),
+                None => errors.push(unknown(&patch.name, format!("components[{}].name", index), manifest)),
+            }
+        }
+
+        for (index, component) in self.add.iter().enumerate() {
+            if manifest.spec.components.iter().any(|existing| existing.name == component.name) {
+                errors.push(source.format_error(&format!("the component {:?} is already in the manifest, change it under `components`", component.name), source.locate(&format!("add[{}].name", index))));
+            } else {
+                manifest.spec.components.push(component.clone());
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+}
+
+impl ComponentOverlay {
+    fn apply(&self, component: &mut Component) {
+        if let Some(image) = &self.image {
+            match &mut component.properties {
+                Properties::Actor { properties } => properties.image = image.clone(),
+                Properties::Capability { properties } => properties.image = image.clone(),
+            }
+        }
+
+        let traits = component.traits.get_or_insert_with(Vec::new);
+
+        if let Some(replicas) = self.replicas {
+            let mut scaled = false;
+            for t in traits.iter_mut().filter(|t| t.trait_type == SPREADSCALER_TRAIT || t.trait_type == DAEMONSCALER_TRAIT) {
+                if let TraitProperty::SpreadScaler(props) = &mut t.properties {
+                    props.replicas = replicas;
+                    scaled = true;
+                }
+            }
+            if !scaled {
+                traits.push(Trait::new_spreadscaler(SpreadScalerProperty { replicas, spread: Vec::new() }));
+            }
+        }
+
+        for (target, values) in &self.links {
+            let position = traits.iter()
+                .position(|t| t.trait_type == LINKDEF_TRAIT && matches!(&t.properties, TraitProperty::Linkdef(props) if &props.target == target))
+                .unwrap_or_else(|| {
+                    traits.push(Trait::new_linkdef(LinkdefProperty { target: target.clone(), values: None }));
+                    traits.len() - 1
+                });
+
+            if let TraitProperty::Linkdef(link) = &mut traits[position].properties {
+                let link_values = link.values.get_or_insert_with(HashMap::new);
+                for (key, value) in values {
+                    match value {
+                        Some(value) => link_values.insert(key.clone(), value.clone()),
+                        None => link_values.remove(key),
+                    };
+                }
+            }
+        }
+
+        if traits.is_empty() {
+            component.traits = None;
+        }
+    }
+}
+
+/// Swaps the image of named components for the artifact of a local project, so
+/// that a dependency can be hacked on without editing the manifest. The projects
+/// come from the `local` section of `kuwash.yaml` and from `--local name=path` flags
+pub fn apply_local_overrides(manifest: &mut Manifest, manifest_path: &Path, flags: &[(String, PathBuf)]) -> Result<(), String> {
+    let directory = manifest_path.parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut overrides: BTreeMap<String, PathBuf> = ProjectConfig::load(manifest_path).local.into_iter()
+        .map(|(name, path)| (name, directory.join(path)))
+        .collect();
+    overrides.extend(flags.iter().cloned());
+
+    let mut errors = Vec::new();
+    for (name, project) in overrides {
+        let names = manifest.spec.components.iter().map(|component| component.name.as_str());
+        let hint = suggest(&name, names).map(|candidate| format!(", did you mean {:?}?", candidate)).unwrap_or_default();
+        let component = match manifest.spec.components.iter_mut().find(|component| component.name == name) {
+            Some(component) => component,
+            None => {
+                errors.push(format!("{}: cannot use a local project for {:?}, it is not in the manifest{}", "error".red().bold(), name, hint));
+                continue;
+            }
+        };
+
+        let (image, provider) = match &mut component.properties {
+            Properties::Actor { properties } => (&mut properties.image, false),
+            Properties::Capability { properties } => (&mut properties.image, true),
+        };
+        let artifact = match Helper::local_artifact_path(&project, provider) {
+            Some(artifact) if project.is_dir() => artifact,
+            _ => {
+                errors.push(format!("{}: the local project {:?} for {:?} is not a wasmCloud project directory", "error".red().bold(), project, name));
+                continue;
+            }
+        };
+
+        let artifact = resolve_local_image(&format!("file://{}", artifact.display()), &std::env::current_dir().unwrap_or_default());
+        Logger::info(format!("Using the local project {:?} for {} instead of {}", project, name, image));
+        *image = artifact;
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
@@ -0,0 +1,113 @@
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+use std::time::Duration;
+use colored::*;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+static MULTI_PROGRESS: OnceLock<Option<MultiProgress>> = OnceLock::new();
+
+/// Shared progress output for long running steps (builds, deploys, reconciliation).
+///
+/// When stdout is a TTY the tasks are drawn as indicatif spinners and bars
+/// under a single `MultiProgress`. Otherwise every task degrades to plain lines
+/// so logs from CI or redirected output stay readable.
+pub struct Progress {}
+
+impl Progress {
+    fn multi() -> Option<&'static MultiProgress> {
+        MULTI_PROGRESS.get_or_init(|| {
+            if std::io::stdout().is_terminal() {
+                Some(MultiProgress::new())
+            } else {
+                None
+            }
+        }).as_ref()
+    }
+
+    /// Starts a spinner for a task with an unknown duration
+    pub fn spinner(message: String) -> Task {
+        match Self::multi() {
+            Some(multi) => {
+                let bar = multi.add(ProgressBar::new_spinner());
+                bar.set_style(ProgressStyle::with_template("{spinner:.cyan} {msg} {elapsed:.dim}").unwrap());
+                bar.set_message(message.clone());
+                bar.enable_steady_tick(Duration::from_millis(100));
+                Task { bar: Some(bar), message }
+            }
+            None => {
+                println!("{} {}", "...".dimmed(), message);
+                Task { bar: None, message }
+            }
+        }
+    }
+
+    /// Starts a bar for a task made up of `length` steps
+    pub fn bar(length: u64, message: String) -> Task {
+        match Self::multi() {
+            Some(multi) => {
+                let bar = multi.add(ProgressBar::new(length));
+                bar.set_style(ProgressStyle::with_template("{spinner:.cyan} {msg} [{bar:30.cyan/blue}] {pos}/{len} {elapsed:.dim}").unwrap()
+                    .progress_chars("=> "));
+                bar.set_message(message.clone());
+                bar.enable_steady_tick(Duration::from_millis(100));
+                Task { bar: Some(bar), message }
+            }
+            None => {
+                println!("{} {} (0/{})", "...".dimmed(), message, length);
+                Task { bar: None, message }
+            }
+        }
+    }
+
+    /// Runs `f` with the progress bars hidden so regular output does not get
+    /// mixed up with the redrawn bars
+    pub fn suspend<F: FnOnce() -> R, R>(f: F) -> R {
+        match Self::multi() {
+            Some(multi) => multi.suspend(f),
+            None => f(),
+        }
+    }
+}
+
+/// A single spinner or bar owned by a `Progress` output
+pub struct Task {
+    bar: Option<ProgressBar>,
+    message: String,
+}
+
+impl Task {
+    pub fn set_message(&mut self, message: String) {
+        match &self.bar {
+            Some(bar) => bar.set_message(message.clone()),
+            None => println!("{} {}", "...".dimmed(), message),
+        }
+        self.message = message;
+    }
+
+    pub fn set_position(&self, position: u64) {
+        match &self.bar {
+            Some(bar) => bar.set_position(position),
+            None => println!("{} {} ({})", "...".dimmed(), self.message, position),
+        }
+    }
+
+    pub fn finish(&self, message: String) {
+        match &self.bar {
+            Some(bar) => {
+                bar.set_style(ProgressStyle::with_template("{msg} {elapsed:.dim}").unwrap());
+                bar.finish_with_message(format!("{} {}", "✔".green(), message));
+            }
+            None => println!("{} {}", "✔".green(), message),
+        }
+    }
+
+    pub fn fail(&self, message: String) {
+        match &self.bar {
+            Some(bar) => {
+                bar.set_style(ProgressStyle::with_template("{msg} {elapsed:.dim}").unwrap());
+                bar.abandon_with_message(format!("{} {}", "✘".red(), message));
+            }
+            None => println!("{} {}", "✘".red(), message),
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use nkeys::KeyPair;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use crate::claims::{encode_jwt, PAR_CLAIMS_FILE};
+use crate::logger::Logger;
+use crate::shutdown::TrackedCommand;
+
+/// Claims revision of the wascap format the hosts accept
+const WASCAP_REVISION: u32 = 3;
+/// Vendor wash signs providers with when the project names none
+const DEFAULT_VENDOR: &str = "NONE";
+
+/// A Rust provider project packaged as a `.par.gz` without wash or a Makefile: the binary
+/// cargo builds for the host target, and the claims signed with the project's dev keys
+pub struct ProviderArchive {
+    pub project: PathBuf,
+    pub archive: PathBuf,
+    /// Contract ID of the provider, used when `wasmcloud.toml` sets no `capability_id`
+    pub contract: String,
+}
+
+/// Name, version and signing details of a provider project,
+/// from `wasmcloud.toml` when it has them, `Cargo.toml` otherwise
+#[derive(Debug)]
+struct ProjectMetadata {
+    name: String,
+    capability_id: String,
+    vendor: String,
+    revision: i32,
+    version: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ProviderJwt {
+    jti: String,
+    iat: u64,
+    iss: String,
+    sub: String,
+    wascap: ProviderWascap,
+    wascap_revision: u32,
+}
+
+#[derive(Serialize)]
+struct ProviderWascap {
+    name: String,
+    capid: String,
+    vendor: String,
+    rev: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ver: Option<String>,
+    target_hashes: HashMap<String, String>,
+}
+
+impl ProviderArchive {
+    pub fn new(project: &Path, archive: &Path, contract: &str) -> ProviderArchive {
+        ProviderArchive {
+            project: project.to_path_buf(),
+            archive: archive.to_path_buf(),
+            contract: contract.to_string(),
+        }
+    }
+
+    /// Whether the project is built natively: a cargo project without a Makefile.
+    /// A Makefile takes precedence, it may package the provider in ways kuwash does not know
+    pub fn supports(project: &Path) -> bool {
+        project.join("Cargo.toml").exists() && !project.join("Makefile").exists()
+    }
+
+    /// Builds the provider and writes its archive, replacing the previous one only once
+    /// the new one is complete so hosts and watchers never see a partial file
+    pub fn build(&self, release: bool) -> Result<(), String> {
+        let metadata = self.metadata()?;
+        let binary = self.build_binary(&metadata.name, release)?;
+        let bytes = fs::read(&binary).map_err(|error| format!("Could not read {:?}: {}", binary, error))?;
+
+        let target = host_target();
+        let issuer = dev_key(&metadata.name, "account", KeyPair::new_account)?;
+        let subject = dev_key(&metadata.name, "service", KeyPair::new_service)?;
+        let claims = ProviderJwt {
+            jti: token_id(&subject.public_key()),
+            iat: SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0),
+            iss: issuer.public_key(),
+            sub: subject.public_key(),
+            wascap: ProviderWascap {
+                name: metadata.name.clone(),
+                capid: metadata.capability_id,
+                vendor: metadata.vendor,
+                rev: metadata.revision,
+                ver: metadata.version,
+                target_hashes: HashMap::from([(target.clone(), format!("{:X}", Sha256::digest(&bytes)))]),
+            },
+            wascap_revision: WASCAP_REVISION,
+        };
+        let token = encode_jwt(&claims, &issuer)?;
+
+        if let Some(directory) = self.archive.parent() {
+            fs::create_dir_all(directory).map_err(|error| format!("Could not create {:?}: {}", directory, error))?;
+        }
+        let partial = self.archive.with_extension("gz.partial");
+        write_archive(&partial, &token, &format!("{}.bin", target), &bytes)
+            .map_err(|error| format!("Could not write {:?}: {}", partial, error))?;
+        fs::rename(&partial, &self.archive).map_err(|error| format!("Could not move the archive to {:?}: {}", self.archive, error))?;
+
+        Logger::info(format!("Packaged {} for {} as {:?}, signed by {}", metadata.name, target, self.archive, issuer.public_key()));
+        Ok(())
+    }
+
+    fn metadata(&self) -> Result<ProjectMetadata, String> {
+        let read = |file: &str| fs::read_to_string(self.project.join(file)).ok().and_then(|content| content.parse::<toml::Table>().ok());
+        let wasmcloud = read("wasmcloud.toml").unwrap_or_default();
+        let cargo = read("Cargo.toml").ok_or_else(|| format!("{:?} has no readable Cargo.toml", self.project))?;
+        let package = cargo.get("package").and_then(|package| package.as_table()).cloned().unwrap_or_default();
+        let provider = wasmcloud.get("provider").and_then(|provider| provider.as_table()).cloned().unwrap_or_default();
+        let string = |table: &toml::Table, key: &str| table.get(key).and_then(|value| value.as_str()).map(String::from);
+
+        let name = string(&wasmcloud, "name").or_else(|| string(&package, "name"))
+            .ok_or_else(|| format!("Neither wasmcloud.toml nor Cargo.toml at {:?} name the provider", self.project))?;
+        let capability_id = string(&provider, "capability_id").unwrap_or_else(|| self.contract.clone());
+        if capability_id.is_empty() {
+            return Err(format!("No contract ID for {}, set `capability_id` under [provider] in wasmcloud.toml", name));
+        }
+
+        Ok(ProjectMetadata {
+            name,
+            capability_id,
+            vendor: string(&provider, "vendor").unwrap_or(DEFAULT_VENDOR.to_string()),
+            revision: wasmcloud.get("revision").and_then(|revision| revision.as_integer()).unwrap_or(0) as i32,
+            version: string(&wasmcloud, "version").or_else(|| string(&package, "version")),
+        })
+    }
+
+    /// Runs cargo for the host target and returns the executable it built,
+    /// the one named after the provider when the project has several
+    fn build_binary(&self, name: &str, release: bool) -> Result<PathBuf, String> {
+        let mut command = process::Command::new("cargo");
+        command.current_dir(&self.project).args(["build", "--message-format=json-render-diagnostics"]);
+        if release {
+            command.arg("--release");
+        }
+        let output = command.tracked_output().map_err(|error| format!("Failed to execute cargo binary: {}", error))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).to_string());
+        }
+
+        let executables: Vec<(String, PathBuf)> = String::from_utf8_lossy(&output.stdout).lines()
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+            .filter(|message| message["reason"] == "compiler-artifact")
+            .filter_map(|message| {
+                let executable = message["executable"].as_str()?;
+                Some((message["target"]["name"].as_str().unwrap_or_default().to_string(), PathBuf::from(executable)))
+            })
+            .collect();
+
+        executables.iter()
+            .find(|(target, _)| target.replace('-', "_") == name.replace('-', "_"))
+            .or(executables.last())
+            .map(|(_, executable)| executable.clone())
+            .ok_or_else(|| format!("cargo built no executable at {:?}", self.project))
+    }
+}
+
+/// The target key hosts look their binary up by, e.g. `x86_64-linux`
+pub fn host_target() -> String {
+    format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS)
+}
+
+/// Directory wash keeps its generated keys in, `$WASH_KEYS` or `~/.wash/keys`
+fn keys_directory() -> PathBuf {
+    std::env::var_os("WASH_KEYS").map(PathBuf::from)
+        .unwrap_or_else(|| dirs::home_dir().unwrap_or_else(std::env::temp_dir).join(".wash").join("keys"))
+}
+
+/// The `<name>_<kind>.nk` dev key wash would sign the provider with, generated on first use.
+/// The seed is only readable by its owner, and a key another build generated meanwhile is used as is
+fn dev_key(name: &str, kind: &str, generate: fn() -> KeyPair) -> Result<KeyPair, String> {
+    let path = keys_directory().join(format!("{}_{}.nk", name, kind));
+    let read = || fs::read_to_string(&path).ok().map(|seed| {
+        KeyPair::from_seed(seed.trim()).map_err(|error| format!("{:?} is not a valid key: {}", path, error))
+    });
+    if let Some(key) = read() {
+        return key;
+    }
+
+    let key = generate();
+    let seed = key.seed().map_err(|error| error.to_string())?;
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory).map_err(|error| format!("Could not create {:?}: {}", directory, error))?;
+    }
+    // Written aside and linked into place, so a concurrent build never reads a partial seed
+    let partial = path.with_extension(format!("nk.{}.tmp", process::id()));
+    fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(&partial)
+        .and_then(|mut file| file.write_all(seed.as_bytes()))
+        .map_err(|error| format!("Could not write {:?}: {}", partial, error))?;
+    let linked = fs::hard_link(&partial, &path);
+    let _ = fs::remove_file(&partial);
+    match linked {
+        Ok(()) => {}
+        Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+            return read().unwrap_or_else(|| Err(format!("Could not read {:?}", path)));
+        }
+        Err(error) => return Err(format!("Could not write {:?}: {}", path, error)),
+    }
+    Logger::info(format!("Generated the {} key {:?}", kind, path));
+    Ok(key)
+}
+
+/// A unique ID for the token, like the nuid wascap gives its claims
+fn token_id(subject: &str) -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_nanos()).unwrap_or(0);
+    let digest = Sha256::digest(format!("{}{}{}", subject, nanos, process::id()));
+    URL_SAFE_NO_PAD.encode(digest)[..22].to_string()
+}
+
+fn write_archive(path: &Path, token: &str, binary_name: &str, binary: &[u8]) -> std::io::Result<()> {
+    let mut archive = tar::Builder::new(GzEncoder::new(fs::File::create(path)?, Compression::best()));
+    append(&mut archive, PAR_CLAIMS_FILE, token.as_bytes(), 0o644)?;
+    append(&mut archive, binary_name, binary, 0o755)?;
+    archive.into_inner()?.finish()?.flush()
+}
+
+fn append<W: Write>(archive: &mut tar::Builder<W>, name: &str, content: &[u8], mode: u32) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(mode);
+    header.set_cksum();
+    archive.append_data(&mut header, name, content)
+}
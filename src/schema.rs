@@ -0,0 +1,564 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use wadm::model::{ActorProperties, CapabilityConfig, CapabilityProperties, Component, LinkdefProperty, Manifest, Metadata, Properties, Specification, SpreadScalerProperty, Trait, TraitProperty, DAEMONSCALER_TRAIT, SPREADSCALER_TRAIT};
+use crate::manifest::ManifestSource;
+
+/// Trait linking two components in the components schema, replacing `linkdef`
+pub const LINK_TRAIT: &str = "link";
+/// Annotation keeping what the internal model has no field for, so the manifest
+/// can be written back in the schema it was read in. It never reaches wadm
+pub const SCHEMA_ANNOTATION: &str = "kuwash.dev/schema";
+
+/// The generations of the wadm manifest schema kuwash reads. Both are `core.oam.dev/v1beta1`,
+/// they are told apart by their shape. Every generation is turned into the `wadm::model::Manifest`
+/// kuwash works on and written back in its own generation when put or rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schema {
+    /// `actor` and `capability` components linked by `linkdef` traits, wadm up to 0.10
+    Actors,
+    /// `component` and `capability` components with IDs and named config,
+    /// linked by `link` traits, wadm 0.11 and later
+    Components,
+}
+
+impl Schema {
+    /// The generation of a manifest, `Actors` unless it uses anything only the components schema has
+    pub fn detect(document: &Value) -> Schema {
+        let components = document.pointer("/spec/components").and_then(Value::as_array).cloned().unwrap_or_default();
+        let newer = document.pointer("/spec/policies").is_some() || components.iter().any(|component| {
+            component.get("type").and_then(Value::as_str) == Some("component")
+                || component.pointer("/properties/id").is_some()
+                || component.pointer("/properties/config").map(Value::is_array).unwrap_or(false)
+                || component.get("traits").and_then(Value::as_array).into_iter().flatten()
+                    .any(|t| t.get("type").and_then(Value::as_str) == Some(LINK_TRAIT))
+        });
+
+        if newer { Schema::Components } else { Schema::Actors }
+    }
+
+    /// The generation a manifest was read in
+    pub fn of(manifest: &Manifest) -> Schema {
+        match manifest.metadata.annotations.contains_key(SCHEMA_ANNOTATION) {
+            true => Schema::Components,
+            false => Schema::Actors,
+        }
+    }
+}
+
+/// Parses a manifest of any generation, errors point at the offending line with a code frame
+pub fn parse(source: &ManifestSource) -> Result<Manifest, String> {
+    match Schema::detect(&source.deserialize()?) {
+        Schema::Actors => source.deserialize(),
+        Schema::Components => source.deserialize::<ComponentsManifest>().map(ComponentsManifest::into_manifest),
+    }
+}
+
+/// Turns a manifest of any generation returned by wadm into the internal model
+pub fn from_value(document: Value) -> Result<Manifest, String> {
+    match Schema::detect(&document) {
+        Schema::Actors => serde_json::from_value(document),
+        Schema::Components => serde_json::from_value::<ComponentsManifest>(document).map(ComponentsManifest::into_manifest),
+    }.map_err(|error| error.to_string())
+}
+
+/// The manifest as YAML in the generation it was read in
+pub fn to_yaml(manifest: &Manifest) -> Result<String, String> {
+    match Schema::of(manifest) {
+        Schema::Actors => serde_yaml::to_string(manifest),
+        Schema::Components => serde_yaml::to_string(&ComponentsManifest::from_manifest(manifest)),
+    }.map_err(|error| error.to_string())
+}
+
+/// The ID the lattice knows a component without claims by: its `id` in the components schema,
+/// otherwise the one wadm derives, `<app name>-<component name>` with both lowercased and
+/// every character but ASCII letters and digits replaced by `_`
+pub fn component_id(manifest: &Manifest, component: &Component) -> String {
+    let normalize = |name: &str| name.to_lowercase().replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+    Extensions::of(manifest).components.get(&component.name)
+        .and_then(|extension| extension.id.clone())
+        .unwrap_or_else(|| format!("{}-{}", normalize(&manifest.metadata.name), normalize(&component.name)))
+}
+
+/// The type and properties of a component as written in the generation the manifest was read in
+pub fn component_properties(manifest: &Manifest, component: &Component) -> Value {
+    match Schema::of(manifest) {
+        Schema::Actors => serde_json::to_value(&component.properties).unwrap_or_default(),
+        Schema::Components => {
+            let component = schema_component(component, &manifest.spec.components, &Extensions::of(manifest));
+            json!({ "type": component.component_type, "properties": component.properties })
+        }
+    }
+}
+
+/// The component a trait links to, for `linkdef` and `link` traits
+pub fn link_target(t: &Trait) -> Option<&str> {
+    match &t.properties {
+        TraitProperty::Linkdef(props) => Some(&props.target),
+        TraitProperty::Custom(props) if t.trait_type == LINK_TRAIT => link_target_name(props),
+        _ => None,
+    }
+}
+
+/// What the internal model has no field for, kept in `SCHEMA_ANNOTATION`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Extensions {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    policies: Vec<Value>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    components: BTreeMap<String, ComponentExtension>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ComponentExtension {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    config: Vec<Value>,
+}
+
+impl Extensions {
+    fn of(manifest: &Manifest) -> Extensions {
+        manifest.metadata.annotations.get(SCHEMA_ANNOTATION)
+            .and_then(|extensions| serde_json::from_str(extensions).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// A manifest of the components schema
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ComponentsManifest {
+    api_version: String,
+    kind: String,
+    metadata: Metadata,
+    spec: ComponentsSpecification,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ComponentsSpecification {
+    components: Vec<ComponentsComponent>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    policies: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ComponentsComponent {
+    name: String,
+    #[serde(rename = "type")]
+    component_type: ComponentType,
+    properties: ComponentsProperties,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    traits: Vec<ComponentsTrait>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ComponentType {
+    Component,
+    Capability,
+    /// Still accepted by newer wadm releases
+    Actor,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ComponentsProperties {
+    image: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    /// Named config, either references to config stored in the lattice or inline properties
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    config: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ComponentsTrait {
+    #[serde(rename = "type")]
+    trait_type: String,
+    properties: Value,
+}
+
+impl ComponentsManifest {
+    fn into_manifest(self) -> Manifest {
+        let mut extensions = Extensions { policies: self.spec.policies, components: BTreeMap::new() };
+        let components = self.spec.components.iter()
+            .map(|component| {
+                extensions.components.insert(component.name.clone(), ComponentExtension {
+                    id: component.properties.id.clone(),
+                    config: component.properties.config.clone(),
+                });
+                internal_component(component, &self.spec.components)
+            })
+            .collect();
+
+        let mut metadata = self.metadata;
+        metadata.annotations.insert(SCHEMA_ANNOTATION.to_string(), serde_json::to_string(&extensions).unwrap_or_default());
+        Manifest {
+            api_version: self.api_version,
+            kind: self.kind,
+            metadata,
+            spec: Specification { components },
+        }
+    }
+
+    fn from_manifest(manifest: &Manifest) -> ComponentsManifest {
+        let extensions = Extensions::of(manifest);
+        let mut metadata = manifest.metadata.clone();
+        metadata.annotations.remove(SCHEMA_ANNOTATION);
+
+        let components = manifest.spec.components.iter()
+            .map(|component| schema_component(component, &manifest.spec.components, &extensions))
+            .collect();
+
+        ComponentsManifest {
+            api_version: manifest.api_version.clone(),
+            kind: manifest.kind.clone(),
+            metadata,
+            spec: ComponentsSpecification { components, policies: extensions.policies },
+        }
+    }
+}
+
+fn schema_component(component: &Component, components: &[Component], extensions: &Extensions) -> ComponentsComponent {
+    let extension = extensions.components.get(&component.name).cloned().unwrap_or_default();
+    let (component_type, image) = match &component.properties {
+        Properties::Actor { properties } => (ComponentType::Component, properties.image.clone()),
+        Properties::Capability { properties } => (ComponentType::Capability, properties.image.clone()),
+    };
+    let mut traits = Vec::new();
+    for t in component.traits.iter().flatten() {
+        match &t.properties {
+            // Only profiles add linkdefs to a manifest of this generation
+            TraitProperty::Linkdef(linkdef) => add_linkdef(&mut traits, component, linkdef, components),
+            _ => traits.push(schema_trait(t)),
+        }
+    }
+
+    ComponentsComponent {
+        name: component.name.clone(),
+        component_type,
+        properties: ComponentsProperties { image, id: extension.id, config: extension.config },
+        traits,
+    }
+}
+
+/// A linkdef as a link trait. Its values become inline config of the capability end of the link,
+/// merged into the unnamed link to the same target when the component has one. A new link takes
+/// its namespace, package and interfaces from another link of the capability, or from its contract
+fn add_linkdef(traits: &mut Vec<ComponentsTrait>, component: &Component, linkdef: &LinkdefProperty, components: &[Component]) {
+    let target_is_capability = components.iter()
+        .any(|target| target.name == linkdef.target && matches!(target.properties, Properties::Capability { .. }));
+    let (capability, end) = match target_is_capability {
+        true => (linkdef.target.as_str(), "target"),
+        false => (component.name.as_str(), "source"),
+    };
+
+    let position = traits.iter()
+        .position(|t| t.trait_type == LINK_TRAIT && link_target_name(&t.properties) == Some(linkdef.target.as_str()) && t.properties.get("name").is_none())
+        .unwrap_or_else(|| {
+            traits.push(ComponentsTrait { trait_type: LINK_TRAIT.to_string(), properties: new_link(&linkdef.target, capability, components) });
+            traits.len() - 1
+        });
+
+    let values: serde_json::Map<String, Value> = linkdef.values.iter().flatten()
+        .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+        .collect();
+    if values.is_empty() {
+        return;
+    }
+
+    let config = link_config(&mut traits[position].properties, end);
+    match config.iter_mut().find_map(|config| config.get_mut("properties").and_then(Value::as_object_mut)) {
+        Some(properties) => properties.extend(values),
+        None => config.push(json!({ "name": format!("{}-{}", component.name, linkdef.target), "properties": values })),
+    }
+}
+
+/// A link to `target` speaking the interfaces `capability` is linked with elsewhere in the manifest
+fn new_link(target: &str, capability: &str, components: &[Component]) -> Value {
+    let known = components.iter()
+        .flat_map(|source| source.traits.iter().flatten().map(move |t| (source, t)))
+        .filter(|(_, t)| t.trait_type == LINK_TRAIT)
+        .filter_map(|(source, t)| match &t.properties {
+            TraitProperty::Custom(properties) => Some((source, properties)),
+            _ => None,
+        })
+        .find(|(source, properties)| source.name == capability || link_target_name(properties) == Some(capability))
+        .map(|(_, properties)| properties);
+
+    let (namespace, package, interfaces) = match known {
+        Some(link) => (link["namespace"].clone(), link["package"].clone(), link["interfaces"].clone()),
+        None => {
+            let contract = components.iter()
+                .find_map(|component| match &component.properties {
+                    Properties::Capability { properties } if component.name == capability => Some(properties.contract.as_str()),
+                    _ => None,
+                })
+                .unwrap_or_default();
+            let (namespace, package) = contract.split_once(':').unwrap_or((contract, ""));
+            (json!(namespace), json!(package), json!([]))
+        }
+    };
+    json!({ "target": target, "namespace": namespace, "package": package, "interfaces": interfaces })
+}
+
+/// The config list of one end of a link, `<end>_config` or `config` of an `<end>` object
+fn link_config<'a>(link: &'a mut Value, end: &str) -> &'a mut Vec<Value> {
+    let nested = link.get(end).map(Value::is_object).unwrap_or(false);
+    let config = match nested {
+        true => &mut link[end]["config"],
+        false => &mut link[format!("{}_config", end)],
+    };
+    if !config.is_array() {
+        *config = json!([]);
+    }
+    config.as_array_mut().unwrap()
+}
+
+/// A component in the internal model. Capabilities get the contract of their first link,
+/// e.g. `wasi:http`, and their inline config
+fn internal_component(component: &ComponentsComponent, components: &[ComponentsComponent]) -> Component {
+    let image = component.properties.image.clone();
+    let properties = match component.component_type {
+        ComponentType::Component | ComponentType::Actor => Properties::Actor { properties: ActorProperties { image } },
+        ComponentType::Capability => {
+            let targeting = components.iter()
+                .flat_map(|source| source.traits.iter())
+                .filter(|t| link_target_name(&t.properties) == Some(component.name.as_str()));
+            let link = component.traits.iter().chain(targeting).find(|t| t.trait_type == LINK_TRAIT);
+            // Left empty rather than half a contract when the link names no namespace or package
+            let contract = link
+                .and_then(|t| {
+                    let part = |field: &str| t.properties[field].as_str().filter(|part| !part.is_empty());
+                    Some(format!("{}:{}", part("namespace")?, part("package")?))
+                })
+                .unwrap_or_default();
+            let link_name = link.and_then(|t| t.properties["name"].as_str()).map(String::from);
+            let config = inline_config(&component.properties.config);
+            Properties::Capability { properties: CapabilityProperties { image, contract, link_name, config } }
+        }
+    };
+
+    let traits: Vec<Trait> = component.traits.iter().map(internal_trait).collect();
+    Component {
+        name: component.name.clone(),
+        properties,
+        traits: if traits.is_empty() { None } else { Some(traits) },
+    }
+}
+
+/// Scalers count `instances` instead of `replicas`, other traits are kept as they are
+fn internal_trait(t: &ComponentsTrait) -> Trait {
+    let scaler = (t.trait_type == SPREADSCALER_TRAIT || t.trait_type == DAEMONSCALER_TRAIT)
+        .then(|| {
+            let mut properties = t.properties.clone();
+            if let Some(instances) = properties.as_object_mut().and_then(|properties| properties.remove("instances")) {
+                properties["replicas"] = instances;
+            }
+            serde_json::from_value::<SpreadScalerProperty>(properties).ok()
+        })
+        .flatten();
+
+    Trait {
+        trait_type: t.trait_type.clone(),
+        properties: match scaler {
+            Some(scaler) => TraitProperty::SpreadScaler(scaler),
+            None => TraitProperty::Custom(t.properties.clone()),
+        },
+    }
+}
+
+fn schema_trait(t: &Trait) -> ComponentsTrait {
+    let properties = match &t.properties {
+        TraitProperty::SpreadScaler(scaler) => {
+            let mut properties = serde_json::to_value(scaler).unwrap_or_default();
+            if let Some(fields) = properties.as_object_mut() {
+                if let Some(replicas) = fields.remove("replicas") {
+                    fields.insert("instances".to_string(), replicas);
+                }
+                if fields.get("spread").and_then(Value::as_array).map(Vec::is_empty).unwrap_or(false) {
+                    fields.remove("spread");
+                }
+            }
+            properties
+        }
+        // Turned into links by `add_linkdef`
+        TraitProperty::Linkdef(linkdef) => serde_json::to_value(linkdef).unwrap_or_default(),
+        TraitProperty::Custom(properties) => properties.clone(),
+    };
+    ComponentsTrait { trait_type: t.trait_type.clone(), properties }
+}
+
+fn link_target_name(properties: &Value) -> Option<&str> {
+    match properties.get("target")? {
+        Value::String(target) => Some(target),
+        target => target.get("name").and_then(Value::as_str),
+    }
+}
+
+/// The inline properties of the named config merged, what a capability's config was before
+fn inline_config(config: &[Value]) -> Option<CapabilityConfig> {
+    let merged: serde_json::Map<String, Value> = config.iter()
+        .filter_map(|config| config.get("properties").and_then(Value::as_object))
+        .flat_map(|properties| properties.clone())
+        .collect();
+
+    if merged.is_empty() { None } else { Some(CapabilityConfig::Json(Value::Object(merged))) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::Path;
+    use super::*;
+
+    const COMPONENTS: &str = "
+apiVersion: core.oam.dev/v1beta1
+kind: Application
+metadata:
+  name: Hello.World
+  annotations:
+    version: v0.0.1
+spec:
+  components:
+    - name: http-component
+      type: component
+      properties:
+        image: ghcr.io/wasmcloud/components/http-hello-world-rust:0.1.0
+    - name: named
+      type: component
+      properties:
+        image: ghcr.io/wasmcloud/components/http-hello-world-rust:0.1.0
+        id: hello-named
+    - name: httpserver
+      type: capability
+      properties:
+        image: ghcr.io/wasmcloud/http-server:0.20.0
+        config:
+          - name: default-http
+            properties:
+              address: 0.0.0.0:8080
+      traits:
+        - type: spreadscaler
+          properties:
+            instances: 2
+        - type: link
+          properties:
+            target: http-component
+            name: public
+            namespace: wasi
+            package: http
+            interfaces: [incoming-handler]
+            source_config:
+              - name: default-http
+                properties:
+                  address: 0.0.0.0:8080
+";
+
+    fn parse_text(text: &str) -> Manifest {
+        parse(&ManifestSource::new(Path::new("wadm.yaml"), text.to_string())).unwrap()
+    }
+
+    fn component<'a>(manifest: &'a Manifest, name: &str) -> &'a Component {
+        manifest.spec.components.iter().find(|component| component.name == name).unwrap()
+    }
+
+    #[test]
+    fn derives_component_ids_like_wadm() {
+        let manifest = parse_text(COMPONENTS);
+        assert_eq!(component_id(&manifest, component(&manifest, "http-component")), "hello_world-http_component");
+        assert_eq!(component_id(&manifest, component(&manifest, "named")), "hello-named");
+    }
+
+    fn document(text: &str) -> Value {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn detects_the_schema_by_shape() {
+        assert_eq!(Schema::detect(&document(COMPONENTS)), Schema::Components);
+        assert_eq!(Schema::detect(&json!({ "spec": { "components": [] } })), Schema::Actors);
+        assert_eq!(Schema::detect(&json!({ "spec": { "components": [{ "type": "actor", "properties": { "image": "a" } }] } })), Schema::Actors);
+        assert_eq!(Schema::detect(&json!({ "spec": { "components": [], "policies": [] } })), Schema::Components);
+        assert_eq!(Schema::detect(&json!({ "spec": { "components": [{ "type": "capability", "properties": { "image": "a", "config": [] } }] } })), Schema::Components);
+        assert_eq!(Schema::detect(&json!({ "spec": { "components": [{ "type": "capability", "traits": [{ "type": "link" }] }] } })), Schema::Components);
+        assert_eq!(Schema::detect(&json!("not a manifest")), Schema::Actors);
+    }
+
+    #[test]
+    fn writes_a_manifest_back_in_the_schema_it_was_read_in() {
+        let manifest = parse_text(COMPONENTS);
+        assert_eq!(Schema::of(&manifest), Schema::Components);
+        let written: Value = serde_yaml::from_str(&to_yaml(&manifest).unwrap()).unwrap();
+        assert_eq!(written, document(COMPONENTS));
+        assert_eq!(from_value(written).unwrap(), manifest);
+    }
+
+    #[test]
+    fn reads_scalers_and_contracts_into_the_internal_model() {
+        let manifest = parse_text(COMPONENTS);
+        let httpserver = component(&manifest, "httpserver");
+        match &httpserver.properties {
+            Properties::Capability { properties } => {
+                assert_eq!(properties.contract, "wasi:http");
+                assert_eq!(properties.link_name.as_deref(), Some("public"));
+                assert_eq!(properties.config, Some(CapabilityConfig::Json(json!({ "address": "0.0.0.0:8080" }))));
+            }
+            other => panic!("{:?}", other),
+        }
+        let scaler = httpserver.traits.iter().flatten().find(|t| t.trait_type == SPREADSCALER_TRAIT).unwrap();
+        assert!(matches!(&scaler.properties, TraitProperty::SpreadScaler(scaler) if scaler.replicas == 2));
+    }
+
+    #[test]
+    fn leaves_the_contract_empty_when_links_name_no_interface() {
+        let manifest = parse_text(&COMPONENTS.replace("            namespace: wasi\n", ""));
+        match &component(&manifest, "httpserver").properties {
+            Properties::Capability { properties } => assert_eq!(properties.contract, ""),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn writes_linkdefs_of_profiles_as_complete_links() {
+        let mut manifest = parse_text(COMPONENTS);
+        let values = HashMap::from([("address".to_string(), "0.0.0.0:9090".to_string())]);
+        for (name, target) in [("httpserver", "http-component"), ("named", "httpserver")] {
+            let component = manifest.spec.components.iter_mut().find(|component| component.name == name).unwrap();
+            component.traits.get_or_insert_with(Vec::new)
+                .push(Trait::new_linkdef(LinkdefProperty { target: target.to_string(), values: Some(values.clone()) }));
+        }
+        let written = serde_json::to_value(ComponentsManifest::from_manifest(&manifest)).unwrap();
+
+        // A new link speaks the interfaces the capability is linked with and configures the capability
+        let named = &written["spec"]["components"][1]["traits"][0]["properties"];
+        assert_eq!(named, &json!({
+            "target": "httpserver",
+            "namespace": "wasi",
+            "package": "http",
+            "interfaces": ["incoming-handler"],
+            "target_config": [{ "name": "named-httpserver", "properties": { "address": "0.0.0.0:9090" } }],
+        }));
+
+        // The only link to the target is named, so it is left alone and a new link is added
+        let httpserver = &written["spec"]["components"][2]["traits"];
+        assert_eq!(httpserver.as_array().unwrap().len(), 3);
+        assert_eq!(httpserver[1]["properties"]["source_config"][0]["properties"]["address"], "0.0.0.0:8080");
+        assert_eq!(httpserver[2]["properties"]["target"], "http-component");
+        assert_eq!(httpserver[2]["properties"]["source_config"][0]["properties"]["address"], "0.0.0.0:9090");
+    }
+
+    #[test]
+    fn merges_linkdefs_into_the_unnamed_link_to_their_target() {
+        let mut manifest = parse_text(&COMPONENTS.replace("            name: public\n", ""));
+        let values = HashMap::from([("address".to_string(), "0.0.0.0:9090".to_string())]);
+        let httpserver = manifest.spec.components.iter_mut().find(|component| component.name == "httpserver").unwrap();
+        httpserver.traits.get_or_insert_with(Vec::new)
+            .push(Trait::new_linkdef(LinkdefProperty { target: "http-component".to_string(), values: Some(values) }));
+        let written = serde_json::to_value(ComponentsManifest::from_manifest(&manifest)).unwrap();
+
+        let traits = written["spec"]["components"][2]["traits"].as_array().unwrap();
+        assert_eq!(traits.len(), 2);
+        assert_eq!(traits[1]["properties"]["source_config"], json!([{ "name": "default-http", "properties": { "address": "0.0.0.0:9090" } }]));
+    }
+}
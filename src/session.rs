@@ -0,0 +1,176 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::logger::Logger;
+
+/// A component started by a dev session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionComponent {
+    pub name: String,
+    /// Module key of an actor or service key of a provider
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_name: Option<String>,
+}
+
+/// Record of what a running kuwash session has put into the lattice.
+///
+/// It is written to the state directory while the session runs and removed once
+/// the session cleaned up after itself. A record whose pid is no longer alive was
+/// left behind by a crash or SIGKILL and can be removed with `kuwash clean`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub pid: u32,
+    pub app_name: String,
+    /// Lattice the session deployed to, None in records written before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lattice: Option<String>,
+    pub manifest_path: PathBuf,
+    pub started_at: u64,
+    /// App versions put to wadm during the session
+    #[serde(default)]
+    pub versions: Vec<String>,
+    #[serde(default)]
+    pub components: Vec<SessionComponent>,
+}
+
+impl Session {
+    pub fn new(app_name: String, lattice: String, manifest_path: PathBuf, components: Vec<SessionComponent>) -> Self {
+        Session {
+            pid: process::id(),
+            app_name,
+            lattice: Some(lattice),
+            manifest_path,
+            started_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+            versions: Vec::new(),
+            components,
+        }
+    }
+
+    /// Directory holding session records, `$XDG_STATE_HOME/kuwash/sessions` on Linux
+    pub fn directory() -> PathBuf {
+        state_directory().join("sessions")
+    }
+
+    pub fn path(&self) -> PathBuf {
+        Self::directory().join(format!("{}-{}.json", sanitize(&self.app_name), self.pid))
+    }
+
+    pub fn add_version(&mut self, version: &str) {
+        if !self.versions.iter().any(|v| v == version) {
+            self.versions.push(version.to_string());
+        }
+        self.save();
+    }
+
+    /// Writes the record to the state directory. Failures are logged but never fatal
+    pub fn save(&self) {
+        let result = fs::create_dir_all(Self::directory())
+            .and_then(|_| fs::write(self.path(), serde_json::to_vec_pretty(self).unwrap_or_default()));
+
+        if let Err(error) = result {
+            Logger::error(format!("Could not write session record {:?}: {}", self.path(), error));
+        }
+    }
+
+    pub fn remove(&self) -> bool {
+        match fs::remove_file(self.path()) {
+            Ok(_) => true,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => true,
+            Err(error) => {
+                Logger::error(format!("Could not remove session record {:?}: {}", self.path(), error));
+                false
+            }
+        }
+    }
+
+    /// Whether both sessions work on the same app in the same lattice.
+    /// Records without a lattice match the app in any lattice
+    pub fn same_app(&self, other: &Session) -> bool {
+        let same_lattice = match (&self.lattice, &other.lattice) {
+            (Some(lattice), Some(other)) => lattice == other,
+            _ => true,
+        };
+        self.app_name == other.app_name && same_lattice
+    }
+
+    /// Whether the process that owns this session is still running
+    pub fn is_alive(&self) -> bool {
+        is_process_alive(self.pid)
+    }
+
+    /// All session records found in the state directory
+    pub fn load_all() -> Vec<Session> {
+        let entries = match fs::read_dir(Self::directory()) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        entries.filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map(|ext| ext == "json").unwrap_or(false))
+            .filter_map(|path| Self::load(&path))
+            .collect()
+    }
+
+    fn load(path: &Path) -> Option<Session> {
+        let content = fs::read_to_string(path).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(session) => Some(session),
+            Err(error) => {
+                Logger::error(format!("Ignoring unreadable session record {:?}: {}", path, error));
+                None
+            }
+        }
+    }
+}
+
+/// Root of everything kuwash keeps between runs, `$XDG_STATE_HOME/kuwash` on Linux
+pub fn state_directory() -> PathBuf {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("kuwash")
+}
+
+/// A name usable as a single file name in the state directory. A leading dot is replaced
+/// as well, so neither `..` nor hidden files can come out of it
+pub fn sanitize(name: &str) -> String {
+    name.chars().enumerate()
+        .map(|(index, c)| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || (c == '.' && index > 0) { c } else { '_' })
+        .collect()
+}
+
+pub fn is_process_alive(pid: u32) -> bool {
+    // `kill -0` fails for processes owned by other users, prefer procfs where it exists
+    if Path::new("/proc/self").exists() {
+        return Path::new("/proc").join(pid.to_string()).exists();
+    }
+
+    process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_state_file_names_inside_their_directory() {
+        assert_eq!(sanitize("echo-app_1.0"), "echo-app_1.0");
+        assert_eq!(sanitize("../../etc/passwd"), "_._.._etc_passwd");
+        assert_eq!(sanitize(".."), "_.");
+        assert_eq!(sanitize("a/b c"), "a_b_c");
+
+        let session = Session::new("../echo".to_string(), "default".to_string(), PathBuf::from("wadm.yaml"), Vec::new());
+        assert_eq!(session.path().parent(), Some(Session::directory().as_path()));
+        assert_eq!(session.path().file_name().unwrap().to_string_lossy(), format!("_._echo-{}.json", process::id()));
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashSet;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::{self, Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+use colored::*;
+use crate::logger::Logger;
+
+/// Number of SIGINT/SIGTERM/SIGHUP signals received so far
+static SIGNALS: AtomicUsize = AtomicUsize::new(0);
+
+/// Process groups of the child processes (builds, wash calls) that are still running
+static CHILDREN: Mutex<Option<HashSet<u32>>> = Mutex::new(None);
+
+/// The steps the first signal runs, None until a session has something to clean up
+static STEPS: Mutex<Option<Vec<CleanupStep>>> = Mutex::new(None);
+
+/// A single named action run during shutdown, e.g. undeploying the application.
+/// The action returns whether it succeeded.
+pub struct CleanupStep {
+    pub name: String,
+    pub run: Box<dyn FnOnce() -> bool + Send>,
+    /// Only run the step when every step before it succeeded
+    pub requires_success: bool,
+}
+
+impl CleanupStep {
+    pub fn new<F: FnOnce() -> bool + Send + 'static>(name: String, run: F) -> Self {
+        CleanupStep { name, run: Box::new(run), requires_success: false }
+    }
+
+    pub fn after_success<F: FnOnce() -> bool + Send + 'static>(name: String, run: F) -> Self {
+        CleanupStep { name, run: Box::new(run), requires_success: true }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StepStatus {
+    Pending,
+    Running,
+    Done,
+    Failed,
+    Skipped,
+}
+
+/// Tracks what was and was not cleaned up so it can be reported when kuwash exits
+#[derive(Debug, Clone, Default)]
+struct CleanupReport {
+    steps: Arc<Mutex<Vec<(String, StepStatus)>>>,
+}
+
+impl CleanupReport {
+    fn new(steps: &[CleanupStep]) -> Self {
+        CleanupReport {
+            steps: Arc::new(Mutex::new(steps.iter().map(|step| (step.name.clone(), StepStatus::Pending)).collect())),
+        }
+    }
+
+    fn set(&self, index: usize, status: StepStatus) {
+        self.steps.lock().unwrap()[index].1 = status;
+    }
+
+    fn is_complete(&self) -> bool {
+        self.steps.lock().unwrap().iter().all(|(_, status)| *status == StepStatus::Done)
+    }
+
+    fn print(&self) {
+        println!("{}:", "Cleanup report".bold());
+        for (name, status) in self.steps.lock().unwrap().iter() {
+            match status {
+                StepStatus::Done => println!("  {} {}", "✔".green(), name),
+                StepStatus::Failed => println!("  {} {} (failed)", "✘".red(), name),
+                StepStatus::Running => println!("  {} {} (interrupted, not cleaned up)", "✘".red(), name),
+                StepStatus::Pending => println!("  {} {} (not started, not cleaned up)", "-".yellow(), name),
+                StepStatus::Skipped => println!("  {} {} (skipped, an earlier step failed)", "-".yellow(), name),
+            }
+        }
+        println!();
+    }
+}
+
+/// Coordinates shutdown on SIGINT, SIGTERM and SIGHUP.
+///
+/// The first signal kills running child processes and runs the cleanup steps
+/// on a background thread, bounded by a timeout. A second signal forces an exit.
+/// Either way a report of what was and was not cleaned up is printed.
+pub struct Shutdown {}
+
+impl Shutdown {
+    /// Installs the signal handler. It is installed before anything is started, so that
+    /// every child process is stopped whenever the signal comes; the steps to run are
+    /// handed over with `set_steps` as the session acquires things to clean up
+    pub fn on_signal(timeout: Duration) {
+        let started: Mutex<Option<CleanupReport>> = Mutex::new(None);
+
+        ctrlc::set_handler(move || {
+            if SIGNALS.fetch_add(1, Ordering::SeqCst) > 0 {
+                Logger::error("Received a second signal, forcing exit".to_string());
+                Self::kill_children();
+                if let Some(report) = started.lock().unwrap().as_ref() {
+                    report.print();
+                }
+                process::exit(130);
+            }
+
+            Logger::info(format!("Cleaning up (press Ctrl-C again to force exit, giving up after {}s)", timeout.as_secs()));
+            let steps = STEPS.lock().unwrap().take().unwrap_or_default();
+            let report = CleanupReport::new(&steps);
+            *started.lock().unwrap() = Some(report.clone());
+            std::thread::spawn(move || Self::cleanup(steps, report, timeout));
+        }).expect("Error setting signal handler");
+    }
+
+    /// Replaces the steps run on shutdown. Has no effect once shutdown started
+    pub fn set_steps(steps: Vec<CleanupStep>) {
+        let mut current = STEPS.lock().unwrap();
+        if !Self::is_shutting_down() {
+            *current = Some(steps);
+        }
+    }
+
+    /// Whether a shutdown signal has been received
+    pub fn is_shutting_down() -> bool {
+        SIGNALS.load(Ordering::SeqCst) > 0
+    }
+
+    /// Blocks the calling thread for good once shutdown started. Called before exiting on
+    /// a failure, which may just be cleanup killing a child, so the cleanup thread can finish and report
+    pub fn wait_if_shutting_down() {
+        while Self::is_shutting_down() {
+            std::thread::park();
+        }
+    }
+
+    fn cleanup(steps: Vec<CleanupStep>, report: CleanupReport, timeout: Duration) {
+        Self::kill_children();
+
+        let (tx, rx) = mpsc::channel();
+        let worker_report = report.clone();
+        std::thread::spawn(move || {
+            let mut succeeded = true;
+            for (index, step) in steps.into_iter().enumerate() {
+                if step.requires_success && !succeeded {
+                    worker_report.set(index, StepStatus::Skipped);
+                    continue;
+                }
+
+                worker_report.set(index, StepStatus::Running);
+                let status = if (step.run)() { StepStatus::Done } else { StepStatus::Failed };
+                succeeded &= status == StepStatus::Done;
+                worker_report.set(index, status);
+            }
+            let _ = tx.send(());
+        });
+
+        if rx.recv_timeout(timeout).is_err() {
+            Logger::error(format!("Cleanup did not finish within {}s", timeout.as_secs()));
+            Self::kill_children();
+        }
+
+        report.print();
+        process::exit(if report.is_complete() { 0 } else { 1 });
+    }
+
+    fn track_child(pid: u32) {
+        CHILDREN.lock().unwrap().get_or_insert_with(HashSet::new).insert(pid);
+    }
+
+    fn untrack_child(pid: u32) {
+        if let Some(children) = CHILDREN.lock().unwrap().as_mut() {
+            children.remove(&pid);
+        }
+    }
+
+    /// Terminates the process group of every tracked child process
+    pub fn kill_children() {
+        let children: Vec<u32> = CHILDREN.lock().unwrap().iter().flatten().cloned().collect();
+        for pid in children {
+            Logger::info(format!("Stopping child process {pid}"));
+            let _ = Command::new("kill")
+                .args(["-TERM", "--", &format!("-{pid}")])
+                .output();
+        }
+    }
+}
+
+/// Runs a command like `Command::output` while keeping track of it,
+/// so it can be killed when kuwash shuts down
+pub trait TrackedCommand {
+    fn tracked_output(&mut self) -> io::Result<Output>;
+}
+
+impl TrackedCommand for Command {
+    fn tracked_output(&mut self) -> io::Result<Output> {
+        // Own process group: terminal signals go to kuwash only,
+        // and killing the group also stops grandchildren such as cargo or rustc
+        let child = self.process_group(0)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let pid = child.id();
+        Shutdown::track_child(pid);
+        let output = child.wait_with_output();
+        Shutdown::untrack_child(pid);
+        output
+    }
+}
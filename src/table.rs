@@ -0,0 +1,61 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use colored::*;
+
+/// Plain text table with left aligned, space padded columns
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&str]) -> Self {
+        Table {
+            headers: headers.iter().map(|header| header.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn add_row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    pub fn print(&self) {
+        let mut widths: Vec<usize> = self.headers.iter().map(|header| header.chars().count()).collect();
+        for row in &self.rows {
+            for (index, cell) in row.iter().enumerate() {
+                if index < widths.len() {
+                    widths[index] = widths[index].max(cell.chars().count());
+                }
+            }
+        }
+
+        let header = self.headers.iter().enumerate()
+            .map(|(index, header)| pad(header, widths[index]))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", header.trim_end().bold());
+
+        for row in &self.rows {
+            let line = row.iter().enumerate()
+                .map(|(index, cell)| pad(cell, widths.get(index).cloned().unwrap_or_default()))
+                .collect::<Vec<_>>()
+                .join("  ");
+            println!("{}", line.trim_end());
+        }
+    }
+}
+
+fn pad(cell: &str, width: usize) -> String {
+    format!("{}{}", cell, " ".repeat(width.saturating_sub(cell.chars().count())))
+}
+
+/// How long ago a unix timestamp was, e.g. `5m ago`
+pub fn human_age(timestamp: u64) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    match now.saturating_sub(timestamp) {
+        age if age < 60 => format!("{}s ago", age),
+        age if age < 60 * 60 => format!("{}m ago", age / 60),
+        age if age < 60 * 60 * 24 => format!("{}h ago", age / (60 * 60)),
+        age => format!("{}d ago", age / (60 * 60 * 24)),
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use colored::*;
+use wadm::model::{Component, Manifest, Properties, TraitProperty, DAEMONSCALER_TRAIT, LATEST_VERSION, LINKDEF_TRAIT, SPREADSCALER_TRAIT, VERSION_ANNOTATION_KEY};
+use crate::claims::ComponentClaims;
+use crate::helper::Helper;
+use crate::logger::Logger;
+use crate::manifest::{suggest, ManifestSource};
+use crate::schema::{self, link_target, LINK_TRAIT};
+
+/// A single thing wrong with a manifest
+#[derive(Debug, Clone)]
+pub struct Problem {
+    /// Short identifier of the kind of problem, e.g. `dangling-link`
+    pub code: &'static str,
+    /// Name of the component the problem belongs to, None for the manifest itself
+    pub component: Option<String>,
+    /// Path of the offending field, e.g. `spec.components[0].traits[1].properties.target`
+    pub field: String,
+    pub message: String,
+    /// How to fix it, e.g. the component name a misspelled one most likely refers to
+    pub hint: Option<String>,
+}
+
+impl Problem {
+    pub fn new(code: &'static str, component: Option<&Component>, field: String, message: String) -> Problem {
+        Problem {
+            code,
+            component: component.map(|component| component.name.clone()),
+            field,
+            message,
+            hint: None,
+        }
+    }
+
+    pub fn with_hint(mut self, hint: Option<String>) -> Problem {
+        self.hint = hint;
+        self
+    }
+
+    /// The problem with a code frame of the field it is about
+    pub fn render(&self, source: &ManifestSource) -> String {
+        let label = format!("error[{}]", self.code);
+        let mut output = match &self.component {
+            Some(component) => format!("{}: {}: {}\n", label.red().bold(), component, self.message),
+            None => format!("{}: {}\n", label.red().bold(), self.message),
+        };
+
+        let field = match &self.component {
+            Some(component) => source.relocate(&self.field, component),
+            None => Some(self.field.clone()),
+        };
+        match field.as_deref().map(|field| source.locate(field)) {
+            Some(Some(location)) => output.push_str(&source.code_frame(location)),
+            Some(None) => output.push_str(&format!("  {} {} ({})\n", "-->".blue().bold(), source.path.display(), self.field)),
+            None => output.push_str(&format!("  {} added by the profile overlay ({})\n", "-->".blue().bold(), self.field)),
+        }
+        if let Some(hint) = &self.hint {
+            output.push_str(&format!("  {} {}\n", "= help:".bold(), hint));
+        }
+        output
+    }
+}
+
+/// Collects every problem of a manifest instead of stopping at the first one.
+///
+/// Only what can be checked offline is validated. Claims are read from local
+/// artifacts that were already built; components that were not built yet are
+/// checked again once dev mode has built them.
+pub struct Validator<'a> {
+    manifest: &'a Manifest,
+    claims: HashMap<String, ComponentClaims>,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(manifest: &'a Manifest) -> Validator<'a> {
+        Validator {
+            manifest,
+            claims: HashMap::new(),
+        }
+    }
+
+    /// Uses claims that were already read, e.g. after dev mode rebuilt a component
+    pub fn with_claims(mut self, claims: HashMap<String, ComponentClaims>) -> Validator<'a> {
+        self.claims = claims;
+        self
+    }
+
+    /// Checks the traits of every component, and so its links, against the known claims
+    /// without inspecting any artifact
+    pub fn validate_links(&self) -> Vec<Problem> {
+        self.manifest.spec.components.iter().enumerate()
+            .flat_map(|(index, component)| self.check_traits(component, &format!("spec.components[{}]", index)))
+            .collect()
+    }
+
+    pub fn validate(mut self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        problems.extend(self.check_version());
+        problems.extend(self.check_duplicate_names());
+
+        for (index, component) in self.manifest.spec.components.iter().enumerate() {
+            let field = format!("spec.components[{}]", index);
+            problems.extend(self.check_local_path(component, &field));
+            problems.extend(self.check_traits(component, &field));
+        }
+
+        problems
+    }
+
+    fn check_version(&self) -> Vec<Problem> {
+        let field = format!("metadata.annotations.{}", VERSION_ANNOTATION_KEY);
+        match self.manifest.metadata.annotations.get(VERSION_ANNOTATION_KEY) {
+            None => vec![Problem::new("missing-version", None, field, format!("the manifest has no `{}` annotation, wadm deploys and dev sessions track apps by it", VERSION_ANNOTATION_KEY))],
+            Some(version) if version.trim().is_empty() => vec![Problem::new("missing-version", None, field, "the version annotation is empty".to_string())],
+            Some(version) if version == LATEST_VERSION => vec![Problem::new("invalid-version", None, field, format!("`{}` is reserved by wadm and cannot be used as a version", LATEST_VERSION))],
+            Some(_) => Vec::new(),
+        }
+    }
+
+    fn check_duplicate_names(&self) -> Vec<Problem> {
+        let mut seen = HashSet::new();
+        self.manifest.spec.components.iter().enumerate()
+            .filter(|(_, component)| !seen.insert(component.name.as_str()))
+            .map(|(index, component)| Problem::new("duplicate-name", Some(component), format!("spec.components[{}].name", index), format!("the name {:?} is used by more than one component", component.name)))
+            .collect()
+    }
+
+    /// The project of a `file://` image must exist, its artifact may still have to be built
+    fn check_local_path(&mut self, component: &Component, field: &str) -> Vec<Problem> {
+        let field = format!("{}.properties.image", field);
+        let image = image(component);
+        let artifact = match Helper::local_image_path(image) {
+            Some(artifact) => artifact,
+            None => return Vec::new(),
+        };
+
+        let repo_path = Helper::local_repo_path(&artifact);
+        if !Path::new(&repo_path).is_dir() {
+            return vec![Problem::new("missing-path", Some(component), field, format!("the project directory {} of {} does not exist", repo_path, image))];
+        }
+
+        if Path::new(&artifact).exists() {
+            match Helper::try_inspect_image(&artifact, &schema::component_id(self.manifest, component)) {
+                Ok(claims) => {
+                    let problem = match (&component.properties, &claims) {
+                        (Properties::Actor { .. }, ComponentClaims::Provider(_)) => Some("is a provider archive but the component is an actor"),
+                        (Properties::Capability { .. }, ComponentClaims::Actor(_)) => Some("is an actor but the component is a capability"),
+                        (Properties::Capability { .. }, ComponentClaims::Component(_)) => Some("is a wasm component but the component is a capability"),
+                        _ => None,
+                    };
+                    if let Some(problem) = problem {
+                        return vec![Problem::new("wrong-kind", Some(component), field, format!("{} {}", image, problem))];
+                    }
+                    self.claims.insert(component.name.clone(), claims);
+                }
+                Err(error) => {
+                    return vec![Problem::new("unreadable-claims", Some(component), field, format!("the claims of {} cannot be read: {}", image, error.trim()))];
+                }
+            }
+        }
+
+        Vec::new()
+    }
+
+    fn check_traits(&self, component: &Component, field: &str) -> Vec<Problem> {
+        let traits = match &component.traits {
+            Some(traits) => traits,
+            None => return Vec::new(),
+        };
+
+        let mut problems = Vec::new();
+        let mut scalers = 0;
+        for (index, t) in traits.iter().enumerate() {
+            let field = format!("{}.traits[{}]", field, index);
+            match (t.trait_type.as_str(), &t.properties) {
+                (LINKDEF_TRAIT, TraitProperty::Linkdef(props)) => {
+                    problems.extend(self.check_link(component, &props.target, &format!("{}.properties.target", field)));
+                }
+                (SPREADSCALER_TRAIT | DAEMONSCALER_TRAIT, TraitProperty::SpreadScaler(props)) => {
+                    scalers += 1;
+                    let mut names = HashSet::new();
+                    for (spread_index, spread) in props.spread.iter().enumerate() {
+                        let field = format!("{}.properties.spread[{}]", field, spread_index);
+                        if spread.name.trim().is_empty() {
+                            problems.push(Problem::new("invalid-spread", Some(component), format!("{}.name", field), "the spread has no name".to_string()));
+                        } else if !names.insert(spread.name.as_str()) {
+                            problems.push(Problem::new("invalid-spread", Some(component), format!("{}.name", field), format!("the spread name {:?} is used more than once", spread.name)));
+                        }
+                        for (key, value) in &spread.requirements {
+                            if key.trim().is_empty() || value.trim().is_empty() {
+                                problems.push(Problem::new("invalid-spread", Some(component), format!("{}.requirements", field), format!("the requirement {:?}: {:?} needs both a label and a value", key, value)));
+                            }
+                        }
+                    }
+                    if !props.spread.is_empty() && props.spread.iter().all(|spread| spread.weight == Some(0)) {
+                        problems.push(Problem::new("invalid-spread", Some(component), format!("{}.properties.spread", field), "all spread weights are 0, no replica can be placed".to_string()));
+                    }
+                }
+                (LINK_TRAIT, _) => {
+                    problems.extend(self.check_component_link(component, link_target(t), &format!("{}.properties.target", field)));
+                }
+                (LINKDEF_TRAIT | SPREADSCALER_TRAIT | DAEMONSCALER_TRAIT, _) => {
+                    problems.push(Problem::new("invalid-trait", Some(component), format!("{}.properties", field), format!("the properties do not match a {} trait", t.trait_type)));
+                }
+                _ => {}
+            }
+        }
+
+        if scalers > 1 {
+            problems.push(Problem::new("invalid-trait", Some(component), format!("{}.traits", field), format!("{} scaler traits, wadm supports one per component", scalers)));
+        }
+
+        problems
+    }
+
+    /// A link must target a capability of the manifest whose contract or name the actor is signed for
+    fn check_link(&self, component: &Component, target: &str, field: &str) -> Vec<Problem> {
+        if let Properties::Capability { .. } = &component.properties {
+            return vec![Problem::new("invalid-link", Some(component), field.to_string(), "links are declared on the actor, not on the capability".to_string())];
+        }
+
+        let provider = self.manifest.spec.components.iter().find(|c| c.name == target);
+        let contract = match provider.map(|provider| &provider.properties) {
+            Some(Properties::Capability { properties }) => &properties.contract,
+            Some(Properties::Actor { .. }) => {
+                return vec![Problem::new("dangling-link", Some(component), field.to_string(), format!("the link target {:?} is an actor, links target capabilities", target))];
+            }
+            None => {
+                let capabilities = self.manifest.spec.components.iter()
+                    .filter(|c| matches!(c.properties, Properties::Capability { .. }))
+                    .map(|c| c.name.as_str());
+                let hint = suggest(target, capabilities).map(|name| format!("did you mean {:?}?", name));
+                return vec![Problem::new("dangling-link", Some(component), field.to_string(), format!("the link target {:?} is not a component of the manifest", target)).with_hint(hint)];
+            }
+        };
+
+        // Remote actors, actors not built yet and components without claims cannot be checked.
+        // As dev mode always allowed, an actor signed for the provider's name may link to it too
+        if let Some(claims) = self.claims.get(&component.name).and_then(ComponentClaims::as_actor) {
+            let provider_name = match self.claims.get(target) {
+                Some(ComponentClaims::Provider(provider)) => provider.name.as_deref(),
+                _ => None,
+            };
+            let allowed = claims.capabilities.iter()
+                .any(|capability| capability == contract || capability == target || Some(capability.as_str()) == provider_name);
+            if !allowed {
+                return vec![Problem::new("missing-capability", Some(component), field.to_string(), format!("the actor is not signed for {} which {:?} provides, its claims only allow {}", contract, target, claims.capabilities.join(", ")))];
+            }
+        }
+
+        Vec::new()
+    }
+
+    /// A `link` of the components schema may target any component of the manifest.
+    /// Components carry no capability claims, so only the target is checked
+    fn check_component_link(&self, component: &Component, target: Option<&str>, field: &str) -> Vec<Problem> {
+        let target = match target {
+            Some(target) => target,
+            None => return vec![Problem::new("invalid-trait", Some(component), field.to_string(), "the link has no target".to_string())],
+        };
+        if self.manifest.spec.components.iter().any(|c| c.name == target) {
+            return Vec::new();
+        }
+
+        let names = self.manifest.spec.components.iter().map(|c| c.name.as_str());
+        let hint = suggest(target, names).map(|name| format!("did you mean {:?}?", name));
+        vec![Problem::new("dangling-link", Some(component), field.to_string(), format!("the link target {:?} is not a component of the manifest", target)).with_hint(hint)]
+    }
+}
+
+fn image(component: &Component) -> &str {
+    match &component.properties {
+        Properties::Actor { properties } => &properties.image,
+        Properties::Capability { properties } => &properties.image,
+    }
+}
+
+/// Prints every problem of the manifest and exits when there are any
+pub fn preflight(manifest: &Manifest, manifest_path: &Path) {
+    let problems = Validator::new(manifest).validate();
+    if problems.is_empty() {
+        return;
+    }
+
+    print_problems(&problems, manifest_path);
+    println!("{} problem(s) found in {:?}, run `kuwash validate` after fixing them", problems.len(), manifest_path);
+    std::process::exit(1);
+}
+
+pub fn print_problems(problems: &[Problem], manifest_path: &Path) {
+    let source = ManifestSource::read(manifest_path).unwrap_or_else(|error| Logger::error_and_exit(error));
+    for problem in problems {
+        println!("{}", problem.render(&source));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::claims::{ActorClaims, ProviderClaims};
+    use super::*;
+
+    const MANIFEST: &str = "
+apiVersion: core.oam.dev/v1beta1
+kind: Application
+metadata:
+  name: echo
+  annotations:
+    version: v0.0.1
+spec:
+  components:
+    - name: echo
+      type: actor
+      properties:
+        image: wasmcloud.azurecr.io/echo:0.3.8
+      traits:
+        - type: linkdef
+          properties:
+            target: httpserver
+    - name: httpserver
+      type: capability
+      properties:
+        image: wasmcloud.azurecr.io/httpserver:0.17.0
+        contract: wasmcloud:httpserver
+";
+
+    fn codes(manifest: &str, capabilities: &[&str]) -> Vec<&'static str> {
+        let manifest: Manifest = serde_yaml::from_str(manifest).unwrap();
+        let actor = ComponentClaims::Actor(ActorClaims {
+            public_key: "MECHO".to_string(),
+            issuer: "AISSUER".to_string(),
+            name: Some("echo".to_string()),
+            capabilities: capabilities.iter().map(|capability| capability.to_string()).collect(),
+            tags: Vec::new(),
+            revision: None,
+            version: None,
+            call_alias: None,
+        });
+        let provider = ComponentClaims::Provider(ProviderClaims {
+            public_key: "VHTTP".to_string(),
+            issuer: "AISSUER".to_string(),
+            name: Some("HTTP Server".to_string()),
+            contract_id: "wasmcloud:httpserver".to_string(),
+            vendor: "wasmCloud".to_string(),
+            revision: None,
+            version: None,
+            targets: Vec::new(),
+        });
+        let claims = HashMap::from([("echo".to_string(), actor), ("httpserver".to_string(), provider)]);
+        Validator::new(&manifest).with_claims(claims).validate_links().into_iter().map(|problem| problem.code).collect()
+    }
+
+    #[test]
+    fn accepts_actors_signed_for_the_contract_or_name_of_the_provider() {
+        assert!(codes(MANIFEST, &["wasmcloud:httpserver"]).is_empty());
+        assert!(codes(MANIFEST, &["HTTP Server"]).is_empty());
+        assert!(codes(MANIFEST, &["httpserver"]).is_empty());
+        assert_eq!(codes(MANIFEST, &["wasmcloud:keyvalue"]), vec!["missing-capability"]);
+    }
+
+    #[test]
+    fn reports_links_to_missing_components_and_actors() {
+        assert_eq!(codes(&MANIFEST.replace("target: httpserver", "target: httpservr"), &["wasmcloud:httpserver"]), vec!["dangling-link"]);
+        assert_eq!(codes(&MANIFEST.replace("target: httpserver", "target: echo"), &["wasmcloud:httpserver"]), vec!["dangling-link"]);
+    }
+}